@@ -0,0 +1,298 @@
+use crate::IntoRequest;
+use derive_builder::Builder;
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Serialize, Builder)]
+#[builder(pattern = "mutable")]
+pub struct CreateBatchRequest {
+    /// The ID of an uploaded file that contains requests for the new batch. The file must be uploaded with the purpose batch.
+    #[builder(setter(into))]
+    input_file_id: String,
+    /// The endpoint to be used for all requests in the batch.
+    endpoint: BatchEndpoint,
+    /// The time frame within which the batch should be processed. Currently only 24h is supported.
+    #[builder(default)]
+    completion_window: BatchCompletionWindow,
+    /// Optional custom metadata for the batch.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BatchEndpoint {
+    #[serde(rename = "/v1/chat/completions")]
+    ChatCompletions,
+    #[serde(rename = "/v1/embeddings")]
+    Embeddings,
+    #[serde(rename = "/v1/completions")]
+    Completions,
+    /// An endpoint the SDK doesn't know yet, e.g. of batches created elsewhere. Not for creating batches.
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BatchCompletionWindow {
+    #[default]
+    #[serde(rename = "24h")]
+    Hours24,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStatus {
+    /// The input file is being validated before the batch can begin.
+    Validating,
+    /// The input file has failed the validation process.
+    Failed,
+    /// The input file was successfully validated and the batch is currently being run.
+    InProgress,
+    /// The batch has completed and the results are being prepared.
+    Finalizing,
+    /// The batch has been completed and the results are ready.
+    Completed,
+    /// The batch was not able to be completed within the 24-hour time window.
+    Expired,
+    /// The batch is being cancelled (may take up to 10 minutes).
+    Cancelling,
+    /// The batch was cancelled.
+    Cancelled,
+    /// A status the SDK doesn't know yet, not considered terminal.
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchObject {
+    pub id: String,
+    /// The object type, which is always batch.
+    pub object: String,
+    /// The OpenAI API endpoint used by the batch.
+    pub endpoint: BatchEndpoint,
+    #[serde(default)]
+    pub errors: Option<BatchErrors>,
+    /// The ID of the input file for the batch.
+    pub input_file_id: String,
+    /// The time frame within which the batch should be processed.
+    pub completion_window: BatchCompletionWindow,
+    /// The current status of the batch.
+    pub status: BatchStatus,
+    /// The ID of the file containing the outputs of successfully executed requests.
+    #[serde(default)]
+    pub output_file_id: Option<String>,
+    /// The ID of the file containing the outputs of requests with errors.
+    #[serde(default)]
+    pub error_file_id: Option<String>,
+    /// The Unix timestamp (in seconds) for when the batch was created.
+    pub created_at: u64,
+    #[serde(default)]
+    pub in_progress_at: Option<u64>,
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    #[serde(default)]
+    pub finalizing_at: Option<u64>,
+    #[serde(default)]
+    pub completed_at: Option<u64>,
+    #[serde(default)]
+    pub failed_at: Option<u64>,
+    #[serde(default)]
+    pub expired_at: Option<u64>,
+    #[serde(default)]
+    pub cancelling_at: Option<u64>,
+    #[serde(default)]
+    pub cancelled_at: Option<u64>,
+    /// The request counts for different statuses within the batch.
+    #[serde(default)]
+    pub request_counts: Option<BatchRequestCounts>,
+    #[serde(default)]
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchErrors {
+    pub object: String,
+    pub data: Vec<BatchError>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchError {
+    /// An error code identifying the error type.
+    pub code: String,
+    /// A human-readable message providing more details about the error.
+    pub message: String,
+    /// The name of the parameter that caused the error, if applicable.
+    #[serde(default)]
+    pub param: Option<String>,
+    /// The line number of the input file where the error occurred, if applicable.
+    #[serde(default)]
+    pub line: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub struct BatchRequestCounts {
+    /// Total number of requests in the batch.
+    pub total: usize,
+    /// Number of requests that have been completed successfully.
+    pub completed: usize,
+    /// Number of requests that have failed.
+    pub failed: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchList {
+    pub object: String,
+    pub data: Vec<BatchObject>,
+    #[serde(default)]
+    pub first_id: Option<String>,
+    #[serde(default)]
+    pub last_id: Option<String>,
+    pub has_more: bool,
+}
+
+/// Requests on existing batches, which only carry the batch id.
+#[derive(Debug, Clone)]
+pub(crate) enum BatchRequest {
+    List,
+    Retrieve(String),
+    Cancel(String),
+}
+
+impl CreateBatchRequest {
+    pub fn new(input_file_id: impl Into<String>, endpoint: BatchEndpoint) -> Self {
+        CreateBatchRequestBuilder::default()
+            .input_file_id(input_file_id)
+            .endpoint(endpoint)
+            .build()
+            .unwrap()
+    }
+}
+
+impl BatchStatus {
+    /// Whether the batch has reached a final status and will not change anymore.
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            BatchStatus::Failed
+                | BatchStatus::Completed
+                | BatchStatus::Expired
+                | BatchStatus::Cancelled
+        )
+    }
+}
+
+impl IntoRequest for CreateBatchRequest {
+    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        let url = format!("{}/batches", base_url);
+        client.post(url).json(&self)
+    }
+}
+
+impl IntoRequest for BatchRequest {
+    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        match self {
+            BatchRequest::List => client.get(format!("{}/batches", base_url)),
+            BatchRequest::Retrieve(id) => client.get(format!("{}/batches/{}", base_url, id)),
+            BatchRequest::Cancel(id) => client.post(format!("{}/batches/{}/cancel", base_url, id)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FilePurpose, UploadFileRequest, SDK};
+    use anyhow::Result;
+    use serde_json::json;
+
+    #[test]
+    fn create_batch_request_should_serialize() -> Result<()> {
+        let req = CreateBatchRequest::new("file-abc123", BatchEndpoint::ChatCompletions);
+        assert_eq!(
+            serde_json::to_value(req)?,
+            json!({
+              "input_file_id": "file-abc123",
+              "endpoint": "/v1/chat/completions",
+              "completion_window": "24h",
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn batch_object_should_deserialize() -> Result<()> {
+        let batch: BatchObject = serde_json::from_value(json!({
+          "id": "batch_abc123",
+          "object": "batch",
+          "endpoint": "/v1/completions",
+          "errors": null,
+          "input_file_id": "file-abc123",
+          "completion_window": "24h",
+          "status": "completed",
+          "output_file_id": "file-cvaTdG",
+          "error_file_id": "file-HOWS94",
+          "created_at": 1711471533,
+          "in_progress_at": 1711471538,
+          "expires_at": 1711557933,
+          "finalizing_at": 1711493133,
+          "completed_at": 1711493163,
+          "failed_at": null,
+          "expired_at": null,
+          "cancelling_at": null,
+          "cancelled_at": null,
+          "request_counts": { "total": 100, "completed": 95, "failed": 5 },
+          "metadata": { "customer_id": "user_123456789" }
+        }))?;
+        assert_eq!(batch.status, BatchStatus::Completed);
+        assert!(batch.status.is_terminal());
+
+        let unknown: BatchObject = serde_json::from_value(json!({
+          "id": "batch_abc124",
+          "object": "batch",
+          "endpoint": "/v1/responses",
+          "input_file_id": "file-abc124",
+          "completion_window": "24h",
+          "status": "pausing",
+          "created_at": 1711471533
+        }))?;
+        assert_eq!(unknown.endpoint, BatchEndpoint::Unknown);
+        assert_eq!(unknown.status, BatchStatus::Unknown);
+        assert!(!unknown.status.is_terminal());
+        assert_eq!(
+            batch.request_counts,
+            Some(BatchRequestCounts {
+                total: 100,
+                completed: 95,
+                failed: 5
+            })
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn batch_lifecycle_should_work() -> Result<()> {
+        let data = br#"{"custom_id": "request-1", "method": "POST", "url": "/v1/embeddings", "body": {"model": "text-embedding-ada-002", "input": "hello"}}"#;
+        let req = UploadFileRequest::new(data.to_vec(), "batch.jsonl", FilePurpose::Batch);
+        let file = SDK.upload_file(req).await?;
+
+        let req = CreateBatchRequest::new(&file.id, BatchEndpoint::Embeddings);
+        let batch = SDK.create_batch(req).await?;
+        assert_eq!(batch.input_file_id, file.id);
+
+        let retrieved = SDK.retrieve_batch(&batch.id).await?;
+        assert_eq!(retrieved.id, batch.id);
+
+        let batches = SDK.list_batches().await?;
+        assert!(batches.data.iter().any(|b| b.id == batch.id));
+
+        let cancelled = SDK.cancel_batch(&batch.id).await?;
+        assert!(matches!(
+            cancelled.status,
+            BatchStatus::Cancelling | BatchStatus::Cancelled
+        ));
+        Ok(())
+    }
+}
@@ -1,30 +1,56 @@
-use crate::{IntoRequest, ToSchema};
+use crate::{IntoRequest, LlmError, ToSchema};
+use base64::{engine::general_purpose::STANDARD, Engine};
 use derive_builder::Builder;
 use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::RangeInclusive,
+    time::Duration,
+};
 use strum::{Display, EnumIter, EnumMessage, EnumString, EnumVariantNames};
+use tracing::warn;
 
-#[derive(Debug, Clone, Serialize, Builder)]
+#[derive(Debug, Clone, Serialize, Deserialize, Builder)]
+#[builder(build_fn(validate = "Self::validate"))]
 pub struct ChatCompletionRequest {
     /// A list of messages comprising the conversation so far.
     #[builder(setter(into))]
-    messages: Vec<ChatCompletionMessage>,
+    pub(crate) messages: Vec<ChatCompletionMessage>,
     /// ID of the model to use. See the model endpoint compatibility table for details on which models work with the Chat API.
     #[builder(default)]
+    #[serde(default)]
     model: ChatCompleteModel,
     /// Number between -2.0 and 2.0. Positive values penalize new tokens based on their existing frequency in the text so far, decreasing the model's likelihood to repeat the same line verbatim.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
     frequency_penalty: Option<f32>,
-
-    // Modify the likelihood of specified tokens appearing in the completion. Accepts a JSON object that maps tokens (specified by their token ID in the tokenizer) to an associated bias value from -100 to 100. Mathematically, the bias is added to the logits generated by the model prior to sampling. The exact effect will vary per model, but values between -1 and 1 should decrease or increase likelihood of selection; values like -100 or 100 should result in a ban or exclusive selection of the relevant token.
-    // #[builder(default, setter(strip_option))]
-    // #[serde(skip_serializing_if = "Option::is_none")]
-    // logit_bias: Option<f32>,
-    /// The maximum number of tokens to generate in the chat completion.
+    /// Modify the likelihood of specified tokens appearing in the completion. Accepts a JSON object that maps tokens (specified by their token ID in the tokenizer) to an associated bias value from -100 to 100. Mathematically, the bias is added to the logits generated by the model prior to sampling. The exact effect will vary per model, but values between -1 and 1 should decrease or increase likelihood of selection; values like -100 or 100 should result in a ban or exclusive selection of the relevant token.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logit_bias: Option<HashMap<u32, i32>>,
+    /// Whether to return log probabilities of the output tokens or not. If true, returns the log probabilities of each output token returned in the content of message.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logprobs: Option<bool>,
+    /// An integer between 0 and 20 specifying the number of most likely tokens to return at each token position, each with an associated log probability. logprobs must be set to true if this parameter is used.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_logprobs: Option<u8>,
+    /// The maximum number of tokens to generate in the chat completion. Deprecated in favor of max_completion_tokens, and not compatible with o-series reasoning models.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
     max_tokens: Option<usize>,
+    /// An upper bound for the number of tokens that can be generated for a completion, including visible output tokens and reasoning tokens. Required instead of max_tokens for o-series reasoning models.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_completion_tokens: Option<usize>,
+    /// Developer-defined tags and values used for filtering completions in the dashboard. Only used when store is true.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<HashMap<String, String>>,
     /// How many chat completion choices to generate for each input message. Note that you will be charged based on the number of generated tokens across all of the choices. Keep n as 1 to minimize costs.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -33,7 +59,7 @@ pub struct ChatCompletionRequest {
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
     presence_penalty: Option<f32>,
-    /// An object specifying the format that the model must output. Setting to { "type": "json_object" } enables JSON mode, which guarantees the message the model generates is valid JSON.
+    /// An object specifying the format that the model must output. Setting to { "type": "json_object" } enables JSON mode, which guarantees the message the model generates is valid JSON. Setting to { "type": "json_schema", "json_schema": {...} } enables Structured Outputs which ensures the model will match your supplied JSON schema.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
     response_format: Option<ChatResponseFormatObject>,
@@ -42,14 +68,25 @@ pub struct ChatCompletionRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     seed: Option<usize>,
     /// Up to 4 sequences where the API will stop generating further tokens.
-    // TODO: make this as an enum
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
-    stop: Option<String>,
-    /// If set, partial message deltas will be sent, like in ChatGPT. Tokens will be sent as data-only server-sent events as they become available, with the stream terminated by a data: [DONE] message.
+    stop: Option<Stop>,
+    /// Whether or not to store the output of this chat completion request for use in model distillation or evals products.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    store: Option<bool>,
+    /// The latency tier used for processing the request. flex trades a higher latency for a lower price.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    service_tier: Option<ServiceTier>,
+    /// If set, partial message deltas will be sent, like in ChatGPT. Tokens will be sent as data-only server-sent events as they become available, with the stream terminated by a data: [DONE] message. Overridden by the SDK: `chat_stream` sets it to true and `chat_completion` to false.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
+    /// Options for streaming response. Only set this when you set stream: true.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
     /// What sampling temperature to use, between 0 and 2. Higher values like 0.8 will make the output more random, while lower values like 0.2 will make it more focused and deterministic. We generally recommend altering this or top_p but not both.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -60,33 +97,79 @@ pub struct ChatCompletionRequest {
     top_p: Option<f32>,
     /// A list of tools the model may call. Currently, only functions are supported as a tool. Use this to provide a list of functions the model may generate JSON inputs for.
     #[builder(default, setter(into))]
-    #[serde(skip_serializing_if = "Vec::is_empty")]
-    tools: Vec<Tool>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub(crate) tools: Vec<Tool>,
     /// Controls which (if any) function is called by the model. none means the model will not call a function and instead generates a message. auto means the model can pick between generating a message or calling a function. Specifying a particular function via {"type: "function", "function": {"name": "my_function"}} forces the model to call that function. none is the default when no functions are present. auto is the default if functions are present.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
     tool_choice: Option<ToolChoice>,
+    /// Whether to enable parallel function calling during tool use. Set to false to make the model call at most one tool per turn.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parallel_tool_calls: Option<bool>,
     /// A unique identifier representing your end-user, which can help OpenAI to monitor and detect abuse.
     #[builder(default, setter(strip_option, into))]
     #[serde(skip_serializing_if = "Option::is_none")]
     user: Option<String>,
+    /// The timeout of this request, overriding the timeout of the SDK. Not sent to the API.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip)]
+    timeout: Option<Duration>,
+    /// Fail the build instead of logging a warning if both temperature and top_p are altered. Not sent to the API.
+    #[allow(dead_code)]
+    #[builder(default)]
+    #[serde(skip)]
+    strict_sampling: bool,
+    /// Additional parameters merged into the request body, e.g. provider specific ones the SDK doesn't model. The
+    /// keys shall not repeat the fields above.
+    #[builder(default, setter(strip_option))]
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    extra: Option<Map<String, Value>>,
 }
 
-#[derive(
-    Debug, Clone, Default, PartialEq, Eq, Serialize, EnumString, Display, EnumVariantNames,
-)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Stop {
+    /// A single stop sequence.
+    Single(String),
+    /// Up to 4 stop sequences.
+    Many(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
+pub enum ServiceTier {
+    /// The tier configured in the project settings.
+    Auto,
+    Default,
+    Flex,
+    Scale,
+    Priority,
+    /// A tier the SDK doesn't know yet, e.g. of a compatible backend. Not for requests.
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamOptions {
+    /// If set, an additional chunk will be streamed before the data: [DONE] message. The usage field on this chunk shows the token usage statistics for the entire request, and the choices field will always be an empty array. All other chunks will also include a usage field, but with a null value.
+    pub include_usage: bool,
+}
+
+/// Serialized as `"none"`, `"auto"`, `"required"` or `{"type": "function", "function": {"name": "my_function"}}`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, EnumString, Display, EnumVariantNames)]
 pub enum ToolChoice {
     #[default]
     None,
     Auto,
-    // TODO: we need something like this: #[serde(tag = "type", content = "function")]
+    /// The model must call one or more tools, without specifying which.
+    Required,
     Function {
         name: String,
     },
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Tool {
     /// The schema of the tool. Currently, only functions are supported.
     r#type: ToolType,
@@ -94,9 +177,10 @@ pub struct Tool {
     function: FunctionInfo,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionInfo {
     /// A description of what the function does, used by the model to choose when and how to call the function.
+    #[serde(default)]
     description: String,
     /// The name of the function to be called. Must be a-z, A-Z, 0-9, or contain underscores and dashes, with a maximum length of 64.
     name: String,
@@ -104,22 +188,51 @@ pub struct FunctionInfo {
     parameters: serde_json::Value,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatResponseFormatObject {
     r#type: ChatResponseFormat,
+    /// The schema the output must conform to. Only used when type is json_schema.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    json_schema: Option<JsonSchemaFormat>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonSchemaFormat {
+    /// The name of the response format. Must be a-z, A-Z, 0-9, or contain underscores and dashes, with a maximum length of 64.
+    name: String,
+    /// A description of what the response format is for, used by the model to determine how to respond in the format.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    /// The schema for the response format, described as a JSON Schema object.
+    schema: serde_json::Value,
+    /// Whether to enable strict schema adherence when generating the output. If set to true, the model will always follow the exact schema defined in the schema field.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    strict: Option<bool>,
 }
 
 #[derive(
-    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, EnumString, Display, EnumVariantNames,
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    EnumString,
+    Display,
+    EnumVariantNames,
 )]
 #[serde(rename_all = "snake_case")]
 pub enum ChatResponseFormat {
     Text,
     #[default]
+    #[serde(rename = "json_object")]
     Json,
+    JsonSchema,
 }
 
-#[derive(Debug, Clone, Serialize, Display, EnumVariantNames, EnumMessage)]
+#[derive(Debug, Clone, Serialize, Deserialize, Display, EnumVariantNames, EnumMessage)]
 #[serde(rename_all = "snake_case", tag = "role")]
 pub enum ChatCompletionMessage {
     /// A message from a system.
@@ -138,6 +251,7 @@ pub enum ChatCompletionMessage {
     Default,
     PartialEq,
     Eq,
+    Hash,
     Serialize,
     Deserialize,
     EnumString,
@@ -147,8 +261,7 @@ pub enum ChatCompletionMessage {
     EnumMessage,
 )]
 pub enum ChatCompleteModel {
-    /// The default model. Currently, this is the gpt-3.5-turbo-1106 model.
-    #[default]
+    /// GPT-3.5 turbo model. Currently, this is the gpt-3.5-turbo-1106 model.
     #[serde(rename = "gpt-3.5-turbo-1106")]
     #[strum(serialize = "gpt-3.5-turbo")]
     Gpt3Turbo,
@@ -156,20 +269,45 @@ pub enum ChatCompleteModel {
     #[serde(rename = "gpt-3.5-turbo-instruct")]
     #[strum(serialize = "gpt-3.5-turbo-instruct")]
     Gpt3TurboInstruct,
-    /// The latest GPT4 model. Currently, this is the gpt-4-1106-preview model.
+    /// The GPT4 turbo preview model. Currently, this is the gpt-4-1106-preview model.
     #[serde(rename = "gpt-4-1106-preview")]
     #[strum(serialize = "gpt-4-turbo")]
     Gpt4Turbo,
-    /// The latest GPT4 model with vision capability. Currently, this is the gpt-4-1106-vision-preview model.
+    /// The latest GPT-4 turbo model with vision capability, i.e. gpt-4-turbo.
+    #[serde(rename = "gpt-4-turbo")]
+    #[strum(serialize = "gpt-4-turbo-latest")]
+    Gpt4TurboLatest,
+    /// The GPT4 turbo preview model with vision capability. Currently, this is the gpt-4-1106-vision-preview model.
     #[serde(rename = "gpt-4-1106-vision-preview")]
     #[strum(serialize = "gpt-4-turbo-vision")]
     Gpt4TurboVision,
+    /// The flagship GPT-4o model, which accepts text and image inputs.
+    #[serde(rename = "gpt-4o")]
+    #[strum(serialize = "gpt-4o")]
+    Gpt4o,
+    /// The default model. A fast and affordable small GPT-4o model for focused tasks.
+    #[default]
+    #[serde(rename = "gpt-4o-mini")]
+    #[strum(serialize = "gpt-4o-mini")]
+    Gpt4oMini,
+    /// The o1 reasoning model.
+    #[serde(rename = "o1")]
+    #[strum(serialize = "o1")]
+    O1,
+    /// The o3 reasoning model.
+    #[serde(rename = "o3")]
+    #[strum(serialize = "o3")]
+    O3,
+    /// The DeepSeek coder model, e.g. for fill-in-the-middle completion. See `CompletionRequest::new_fim`.
+    #[serde(rename = "deepseek-coder")]
+    #[strum(serialize = "deepseek-coder")]
+    DeepSeekCoder,
 
     #[serde(untagged)]
     Other(String),
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SystemMessage {
     /// The contents of the system message.
     content: String,
@@ -178,31 +316,87 @@ pub struct SystemMessage {
     name: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserMessage {
     /// The contents of the user message.
-    content: String,
+    content: MessageContent,
     /// An optional name for the participant. Provides the model information to differentiate between participants of the same role.
     #[serde(skip_serializing_if = "Option::is_none")]
     name: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MessageContent {
+    /// The text contents of the message.
+    Text(String),
+    /// An array of content parts with a defined type. Image parts are only supported by vision models.
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum ContentPart {
+    /// A text content part.
+    Text { text: String },
+    /// An image content part.
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageUrl {
+    /// Either a URL of the image or the base64 encoded image data.
+    pub url: String,
+    /// Specifies the detail level of the image.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<ImageDetail>,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    EnumString,
+    Display,
+    EnumVariantNames,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ImageDetail {
+    #[default]
+    Auto,
+    Low,
+    High,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AssistantMessage {
     /// The contents of the system message.
     #[serde(default)]
     pub content: Option<String>,
+    /// The reasoning (chain of thought) content, returned by reasoning models like deepseek-reasoner.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub reasoning_content: Option<String>,
     /// An optional name for the participant. Provides the model information to differentiate between participants of the same role.
     #[serde(skip_serializing_if = "Option::is_none", default)]
     pub name: Option<String>,
     /// The tool calls generated by the model, such as function calls.
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub tool_calls: Vec<ToolCall>,
-    #[serde(skip_serializing_if = "Option::is_none", default)]
+    /// The role of the author of a response message. Not serialized, as the role is the tag of `ChatCompletionMessage`.
+    #[serde(skip_serializing, default)]
     pub role: Option<String>,
+    /// The annotations of the content, e.g. the web pages cited by search models. Not sent back to the API.
+    #[serde(skip_serializing, default)]
+    pub annotations: Vec<Annotation>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolMessage {
     /// The contents of the tool message.
     content: String,
@@ -228,6 +422,29 @@ pub struct FunctionCall {
     pub arguments: String,
 }
 
+/// An annotation of the message content.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum Annotation {
+    /// A citation of a web page, e.g. from a web search.
+    UrlCitation { url_citation: UrlCitation },
+    /// An annotation type the SDK doesn't know yet.
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UrlCitation {
+    /// The index of the first character of the cited text in the message content.
+    pub start_index: usize,
+    /// The index after the last character of the cited text in the message content.
+    pub end_index: usize,
+    /// The title of the web page.
+    pub title: String,
+    /// The URL of the web page.
+    pub url: String,
+}
+
 #[derive(
     Debug,
     Clone,
@@ -263,6 +480,13 @@ pub struct ChatCompletionResponse {
     pub object: String,
     /// Usage statistics for the completion request.
     pub usage: ChatCompleteUsage,
+    /// The service tier actually used for processing the request.
+    #[serde(default)]
+    pub service_tier: Option<ServiceTier>,
+    /// Fields the SDK doesn't model, e.g. provider-specific data.
+    #[cfg(feature = "extra-fields")]
+    #[serde(flatten)]
+    pub extra: crate::ExtraFields,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -273,6 +497,41 @@ pub struct ChatCompletionChoice {
     pub index: usize,
     /// A chat completion message generated by the model.
     pub message: AssistantMessage,
+    /// Log probability information for the choice.
+    #[serde(default)]
+    pub logprobs: Option<ChoiceLogProbs>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChoiceLogProbs {
+    /// A list of message content tokens with log probability information.
+    #[serde(default)]
+    pub content: Option<Vec<TokenLogProb>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenLogProb {
+    /// The token.
+    pub token: String,
+    /// The log probability of this token, if it is within the top 20 most likely tokens. Otherwise, the value -9999.0 is used to signify that the token is very unlikely.
+    pub logprob: f32,
+    /// A list of integers representing the UTF-8 bytes representation of the token. Can be null if there is no bytes representation for the token.
+    #[serde(default)]
+    pub bytes: Option<Vec<u8>>,
+    /// List of the most likely tokens and their log probability, at this token position. In rare cases, there may be fewer than the number of requested top_logprobs returned.
+    #[serde(default)]
+    pub top_logprobs: Vec<TopLogProb>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TopLogProb {
+    /// The token.
+    pub token: String,
+    /// The log probability of this token.
+    pub logprob: f32,
+    /// A list of integers representing the UTF-8 bytes representation of the token.
+    #[serde(default)]
+    pub bytes: Option<Vec<u8>>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -283,20 +542,109 @@ pub struct ChatCompleteUsage {
     pub prompt_tokens: usize,
     /// Total number of tokens used in the request (prompt + completion).
     pub total_tokens: usize,
+    /// Breakdown of tokens used in a completion.
+    #[serde(default)]
+    pub completion_tokens_details: Option<CompletionTokensDetails>,
+    /// Breakdown of tokens used in the prompt.
+    #[serde(default)]
+    pub prompt_tokens_details: Option<PromptTokensDetails>,
+}
+
+impl ChatCompleteUsage {
+    /// The number of prompt tokens read from the prompt cache, or 0 if the API doesn't report it.
+    pub fn cached_tokens(&self) -> usize {
+        self.prompt_tokens_details
+            .as_ref()
+            .and_then(|d| d.cached_tokens)
+            .unwrap_or_default()
+    }
+
+    /// The number of tokens generated for reasoning, or 0 if the API doesn't report it.
+    pub fn reasoning_tokens(&self) -> usize {
+        self.completion_tokens_details
+            .as_ref()
+            .and_then(|d| d.reasoning_tokens)
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CompletionTokensDetails {
+    /// Tokens generated by the model for reasoning.
+    #[serde(default)]
+    pub reasoning_tokens: Option<usize>,
+    /// Audio input tokens generated by the model.
+    #[serde(default)]
+    pub audio_tokens: Option<usize>,
+    /// When using Predicted Outputs, the number of tokens in the prediction that appeared in the completion.
+    #[serde(default)]
+    pub accepted_prediction_tokens: Option<usize>,
+    /// When using Predicted Outputs, the number of tokens in the prediction that did not appear in the completion.
+    #[serde(default)]
+    pub rejected_prediction_tokens: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PromptTokensDetails {
+    /// Cached tokens present in the prompt.
+    #[serde(default)]
+    pub cached_tokens: Option<usize>,
+    /// Audio input tokens present in the prompt.
+    #[serde(default)]
+    pub audio_tokens: Option<usize>,
 }
 
 #[derive(Deserialize, Clone, Debug)]
 pub struct Delta {
-    pub content: String,
+    #[serde(default)]
+    pub content: Option<String>,
+    /// The reasoning (chain of thought) fragment, returned by reasoning models like deepseek-reasoner.
+    #[serde(default)]
+    pub reasoning_content: Option<String>,
+    #[serde(default)]
     pub role: Option<String>,
+    /// Fragments of the tool calls generated by the model. Use `ToolCallAccumulator` to reassemble them.
+    #[serde(default)]
+    pub tool_calls: Option<Vec<ToolCallDelta>>,
+    /// The annotations of the content, usually sent in a single chunk.
+    #[serde(default)]
+    pub annotations: Option<Vec<Annotation>>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct ToolCallDelta {
+    /// The index of the tool call this fragment belongs to.
+    pub index: usize,
+    /// The ID of the tool call. Only present in the first fragment.
+    #[serde(default)]
+    pub id: Option<String>,
+    /// The type of the tool. Only present in the first fragment.
+    #[serde(default)]
+    pub r#type: Option<ToolType>,
+    /// The fragment of the function call.
+    #[serde(default)]
+    pub function: Option<FunctionCallDelta>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct FunctionCallDelta {
+    /// The name of the function to call. Only present in the first fragment.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// A fragment of the arguments, to be concatenated with the other fragments.
+    #[serde(default)]
+    pub arguments: Option<String>,
 }
 
 #[derive(Deserialize, Clone, Debug)]
 pub struct ChatStreamChoice {
     pub delta: Delta,
-    pub finish_reason: Option<String>,
+    /// The reason the model stopped generating tokens, only set in the last chunk of the choice.
+    pub finish_reason: Option<FinishReason>,
     pub index: usize,
-    pub logprobs: Option<String>,
+    /// Log probability information of the tokens in this chunk, if logprobs is requested.
+    #[serde(default)]
+    pub logprobs: Option<ChoiceLogProbs>,
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -307,6 +655,16 @@ pub struct ChatStreamResponse {
     pub model: String,
     pub object: String,
     pub system_fingerprint: Option<String>,
+    /// Usage statistics for the completion request. Only present in the last chunk when stream_options.include_usage is set.
+    #[serde(default)]
+    pub usage: Option<ChatCompleteUsage>,
+    /// The service tier actually used for processing the request.
+    #[serde(default)]
+    pub service_tier: Option<ServiceTier>,
+    /// Fields the SDK doesn't model, e.g. provider-specific data.
+    #[cfg(feature = "extra-fields")]
+    #[serde(flatten)]
+    pub extra: crate::ExtraFields,
 }
 
 #[derive(
@@ -319,6 +677,68 @@ pub enum FinishReason {
     Length,
     ContentFilter,
     ToolCalls,
+    /// Deprecated in favor of tool_calls, still returned by some providers.
+    FunctionCall,
+    /// A reason the SDK doesn't know, e.g. a provider-specific one such as `insufficient_system_resource`.
+    #[serde(other)]
+    Unknown,
+}
+
+impl Serialize for ToolChoice {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct FunctionName<'a> {
+            name: &'a str,
+        }
+
+        #[derive(Serialize)]
+        struct FunctionChoice<'a> {
+            r#type: ToolType,
+            function: FunctionName<'a>,
+        }
+
+        match self {
+            ToolChoice::None => serializer.serialize_str("none"),
+            ToolChoice::Auto => serializer.serialize_str("auto"),
+            ToolChoice::Required => serializer.serialize_str("required"),
+            ToolChoice::Function { name } => FunctionChoice {
+                r#type: ToolType::Function,
+                function: FunctionName { name },
+            }
+            .serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ToolChoice {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct FunctionName {
+            name: String,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Mode(String),
+            Function { function: FunctionName },
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Mode(mode) => match mode.as_str() {
+                "none" => Ok(ToolChoice::None),
+                "auto" => Ok(ToolChoice::Auto),
+                "required" => Ok(ToolChoice::Required),
+                _ => Err(de::Error::unknown_variant(
+                    &mode,
+                    &["none", "auto", "required"],
+                )),
+            },
+            Repr::Function { function } => Ok(ToolChoice::Function {
+                name: function.name,
+            }),
+        }
+    }
 }
 
 impl IntoRequest for ChatCompletionRequest {
@@ -326,17 +746,39 @@ impl IntoRequest for ChatCompletionRequest {
         let url = format!("{}/chat/completions", base_url);
         client.post(url).json(&self)
     }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
 }
 
 impl ChatCompletionRequest {
+    /// A request for the messages with the model.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `messages` is empty. Use `try_new` for messages from user input.
     pub fn new(model: ChatCompleteModel, messages: impl Into<Vec<ChatCompletionMessage>>) -> Self {
+        Self::try_new(model, messages).unwrap()
+    }
+
+    /// Like `new`, but return `LlmError::Validation` if `messages` is empty.
+    pub fn try_new(
+        model: ChatCompleteModel,
+        messages: impl Into<Vec<ChatCompletionMessage>>,
+    ) -> Result<Self, LlmError> {
         ChatCompletionRequestBuilder::default()
             .model(model)
             .messages(messages)
             .build()
-            .unwrap()
+            .map_err(|e| LlmError::Validation(e.to_string()))
     }
 
+    /// A request for the messages with the tools the model may call.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `messages` is empty. Use `ChatCompletionRequestBuilder` for messages from user input.
     pub fn new_with_tools(
         model: ChatCompleteModel,
         messages: impl Into<Vec<ChatCompletionMessage>>,
@@ -349,184 +791,1728 @@ impl ChatCompletionRequest {
             .build()
             .unwrap()
     }
-}
 
-impl ChatCompletionMessage {
-    pub fn new_system(content: impl Into<String>, name: &str) -> ChatCompletionMessage {
-        ChatCompletionMessage::System(SystemMessage {
-            content: content.into(),
-            name: Self::get_name(name),
-        })
+    /// A request whose messages start with a stable `prefix`, e.g. the system prompt and large static context,
+    /// followed by the per-request `messages`. Prompt caching (automatic for OpenAI prompts of 1024 tokens or more)
+    /// only applies to an exact prefix match, so keep the prefix identical across requests and put anything that
+    /// varies after it. Check `ChatCompleteUsage::cached_tokens` of the responses to verify the cache hits.
+    ///
+    /// # Panics
+    ///
+    /// Panics if both `prefix` and `messages` are empty. Use `try_new` with the chained messages otherwise.
+    pub fn new_with_prefix(
+        model: ChatCompleteModel,
+        prefix: impl IntoIterator<Item = ChatCompletionMessage>,
+        messages: impl IntoIterator<Item = ChatCompletionMessage>,
+    ) -> Self {
+        let messages: Vec<_> = prefix.into_iter().chain(messages).collect();
+        Self::new(model, messages)
     }
 
-    pub fn new_user(content: impl Into<String>, name: &str) -> ChatCompletionMessage {
-        ChatCompletionMessage::User(UserMessage {
-            content: content.into(),
-            name: Self::get_name(name),
+    pub fn messages(&self) -> &[ChatCompletionMessage] {
+        &self.messages
+    }
+
+    /// Whether any of the messages contains an image part.
+    pub fn has_images(&self) -> bool {
+        self.messages.iter().any(|message| match message {
+            ChatCompletionMessage::User(m) => match m.content() {
+                MessageContent::Parts(parts) => parts
+                    .iter()
+                    .any(|part| matches!(part, ContentPart::ImageUrl { .. })),
+                MessageContent::Text(_) => false,
+            },
+            _ => false,
         })
     }
 
-    fn get_name(name: &str) -> Option<String> {
-        if name.is_empty() {
-            None
-        } else {
-            Some(name.into())
-        }
+    pub fn model(&self) -> &ChatCompleteModel {
+        &self.model
     }
-}
 
-impl Tool {
-    pub fn new_function<T: ToSchema>(
-        name: impl Into<String>,
-        description: impl Into<String>,
-    ) -> Self {
-        let parameters = T::to_schema();
-        Self {
-            r#type: ToolType::Function,
-            function: FunctionInfo {
-                name: name.into(),
-                description: description.into(),
-                parameters,
-            },
-        }
+    pub fn frequency_penalty(&self) -> Option<f32> {
+        self.frequency_penalty
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::{ToSchema, SDK};
-    use anyhow::Result;
-    use schemars::JsonSchema;
+    pub fn logit_bias(&self) -> Option<&HashMap<u32, i32>> {
+        self.logit_bias.as_ref()
+    }
 
-    #[allow(dead_code)]
-    #[derive(Debug, Clone, Deserialize, JsonSchema)]
-    struct GetWeatherArgs {
-        /// The city to get the weather for.
-        pub city: String,
-        /// the unit
-        pub unit: TemperatureUnit,
+    pub fn logprobs(&self) -> Option<bool> {
+        self.logprobs
     }
 
-    #[allow(dead_code)]
-    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, JsonSchema)]
-    enum TemperatureUnit {
-        /// Celsius
-        #[default]
-        Celsius,
-        /// Fahrenheit
-        Fahrenheit,
+    pub fn top_logprobs(&self) -> Option<u8> {
+        self.top_logprobs
     }
 
-    #[derive(Debug, Clone)]
-    struct GetWeatherResponse {
-        temperature: f32,
-        unit: TemperatureUnit,
+    pub fn max_tokens(&self) -> Option<usize> {
+        self.max_tokens
     }
 
-    #[allow(dead_code)]
-    #[derive(Debug, Deserialize, JsonSchema)]
-    struct ExplainMoodArgs {
-        /// The mood to explain.
-        pub name: String,
+    pub fn max_completion_tokens(&self) -> Option<usize> {
+        self.max_completion_tokens
     }
 
-    fn get_weather_forecast(args: GetWeatherArgs) -> GetWeatherResponse {
-        match args.unit {
-            TemperatureUnit::Celsius => GetWeatherResponse {
-                temperature: 22.2,
-                unit: TemperatureUnit::Celsius,
-            },
-            TemperatureUnit::Fahrenheit => GetWeatherResponse {
-                temperature: 72.0,
-                unit: TemperatureUnit::Fahrenheit,
-            },
-        }
+    pub fn metadata(&self) -> Option<&HashMap<String, String>> {
+        self.metadata.as_ref()
     }
 
-    #[test]
-    #[ignore]
-    fn chat_completion_request_tool_choice_function_serialize_should_work() {
-        let req = ChatCompletionRequestBuilder::default()
-            .tool_choice(ToolChoice::Function {
-                name: "my_function".to_string(),
-            })
-            .messages(vec![])
+    pub fn n(&self) -> Option<usize> {
+        self.n
+    }
+
+    pub fn presence_penalty(&self) -> Option<f32> {
+        self.presence_penalty
+    }
+
+    pub fn response_format(&self) -> Option<&ChatResponseFormatObject> {
+        self.response_format.as_ref()
+    }
+
+    pub fn seed(&self) -> Option<usize> {
+        self.seed
+    }
+
+    pub fn stop(&self) -> Option<&Stop> {
+        self.stop.as_ref()
+    }
+
+    pub fn store(&self) -> Option<bool> {
+        self.store
+    }
+
+    pub fn service_tier(&self) -> Option<ServiceTier> {
+        self.service_tier
+    }
+
+    pub fn extra(&self) -> Option<&Map<String, Value>> {
+        self.extra.as_ref()
+    }
+
+    pub fn stream_options(&self) -> Option<&StreamOptions> {
+        self.stream_options.as_ref()
+    }
+
+    pub fn temperature(&self) -> Option<f32> {
+        self.temperature
+    }
+
+    pub fn top_p(&self) -> Option<f32> {
+        self.top_p
+    }
+
+    pub fn tools(&self) -> &[Tool] {
+        &self.tools
+    }
+
+    pub fn tool_choice(&self) -> Option<&ToolChoice> {
+        self.tool_choice.as_ref()
+    }
+
+    pub fn parallel_tool_calls(&self) -> Option<bool> {
+        self.parallel_tool_calls
+    }
+
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    /// The same request with another model, e.g. to retry a base request with a bigger model.
+    pub fn with_model(mut self, model: ChatCompleteModel) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// The same request with another temperature. Unlike the builder, the range is not validated here, the API
+    /// rejects values outside 0 to 2.
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    /// The same request with a timeout overriding the timeout of the SDK.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+impl ChatCompletionResponse {
+    /// The time the chat completion was created.
+    #[cfg(feature = "chrono")]
+    pub fn created_at(&self) -> chrono::DateTime<chrono::Utc> {
+        crate::unix_timestamp(self.created as u64)
+    }
+
+    /// The choices keyed by their index.
+    pub fn choices_by_index(&self) -> BTreeMap<usize, &ChatCompletionChoice> {
+        self.choices.iter().map(|c| (c.index, c)).collect()
+    }
+
+    /// The best choice for best-of-N sampling. If every choice carries logprobs, this is the choice with the
+    /// highest mean token log probability; otherwise it is the choice with the lowest index.
+    pub fn best_choice(&self) -> Option<&ChatCompletionChoice> {
+        let scores: Option<Vec<_>> = self.choices.iter().map(|c| c.mean_logprob()).collect();
+        match scores {
+            Some(scores) if !scores.is_empty() => self
+                .choices
+                .iter()
+                .zip(scores)
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(c, _)| c),
+            _ => self.choices.iter().min_by_key(|c| c.index),
+        }
+    }
+
+    /// Whether the backend configuration changed since a previous response with the `prev` fingerprint.
+    /// Responses without a fingerprint are never reported as changed.
+    ///
+    /// When relying on `seed` for reproducible outputs, keep the `system_fingerprint` of the first
+    /// response and compare every following one against it: a change means the outputs may differ
+    /// even with the same seed and parameters.
+    ///
+    /// ```no_run
+    /// # async fn run(sdk: llm_sdk::LlmSdk, req: llm_sdk::ChatCompletionRequest) -> Result<(), llm_sdk::LlmError> {
+    /// let first = sdk.chat_completion(req.clone()).await?;
+    /// let fingerprint = first.system_fingerprint.clone().unwrap_or_default();
+    /// let next = sdk.chat_completion(req).await?;
+    /// if next.fingerprint_changed_from(&fingerprint) {
+    ///     eprintln!("backend changed, seeded outputs may no longer be reproducible");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn fingerprint_changed_from(&self, prev: &str) -> bool {
+        matches!(&self.system_fingerprint, Some(fp) if fp != prev)
+    }
+}
+
+impl ChatStreamResponse {
+    /// The time the chat completion was created. Each chunk has the same timestamp.
+    #[cfg(feature = "chrono")]
+    pub fn created_at(&self) -> chrono::DateTime<chrono::Utc> {
+        crate::unix_timestamp(self.created as u64)
+    }
+
+    /// The deltas of the chunk keyed by their choice index. When n > 1, the chunks of the choices are
+    /// interleaved, and the index tells which completion a delta belongs to.
+    pub fn deltas(&self) -> impl Iterator<Item = (usize, &Delta)> {
+        self.choices.iter().map(|c| (c.index, &c.delta))
+    }
+}
+
+impl ChatCompletionChoice {
+    /// The tool calls of the message, empty if the model replied with content only.
+    pub fn tool_calls(&self) -> &[ToolCall] {
+        &self.message.tool_calls
+    }
+
+    /// Whether the model asks for tool calls to be dispatched. Checks the tool calls rather than `finish_reason`,
+    /// as some providers report `stop` for replies with tool calls.
+    pub fn wants_tool_call(&self) -> bool {
+        !self.message.tool_calls.is_empty()
+    }
+
+    fn mean_logprob(&self) -> Option<f32> {
+        let content = self.logprobs.as_ref()?.content.as_ref()?;
+        if content.is_empty() {
+            return None;
+        }
+        Some(content.iter().map(|t| t.logprob).sum::<f32>() / content.len() as f32)
+    }
+}
+
+impl ChatCompletionRequestBuilder {
+    /// Stop generating further tokens when the given sequence is generated.
+    pub fn stop_sequence(&mut self, stop: impl Into<String>) -> &mut Self {
+        self.stop = Some(Some(Stop::Single(stop.into())));
+        self
+    }
+
+    /// Stop generating further tokens when any of the given sequences (up to 4) is generated.
+    pub fn stop_sequences(
+        &mut self,
+        stop: impl IntoIterator<Item = impl Into<String>>,
+    ) -> &mut Self {
+        let stop = stop.into_iter().map(Into::into).collect();
+        self.stop = Some(Some(Stop::Many(stop)));
+        self
+    }
+
+    /// Set `user` to the hex encoded SHA-256 hash of `salt` followed by `id`, so that end-users can be told apart
+    /// for abuse monitoring without sending their raw ids.
+    pub fn user_hashed(&mut self, id: impl AsRef<[u8]>, salt: impl AsRef<[u8]>) -> &mut Self {
+        let digest = Sha256::new().chain_update(salt).chain_update(id).finalize();
+        let user = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        self.user = Some(Some(user));
+        self
+    }
+
+    /// Add a parameter to `extra`, merged into the request body.
+    pub fn extra_param(&mut self, key: impl Into<String>, value: impl Into<Value>) -> &mut Self {
+        self.extra
+            .get_or_insert_with(|| Some(Map::new()))
+            .get_or_insert_with(Map::new)
+            .insert(key.into(), value.into());
+        self
+    }
+
+    /// Sample as reproducibly as the API allows: `temperature` 0, `top_p` 1 (the default, i.e. no nucleus sampling)
+    /// and the given `seed`. Determinism is still best effort, check `system_fingerprint` of the responses for backend
+    /// changes.
+    pub fn deterministic(&mut self, seed: usize) -> &mut Self {
+        self.temperature(0.0).top_p(1.0).seed(seed)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if matches!(&self.messages, Some(messages) if messages.is_empty()) {
+            return Err("messages must contain at least one message".into());
+        }
+        if let Some(Some(top_logprobs)) = self.top_logprobs {
+            if top_logprobs > 20 {
+                return Err(format!(
+                    "top_logprobs must be between 0 and 20, got {}",
+                    top_logprobs
+                ));
+            }
+            if !matches!(self.logprobs, Some(Some(true))) {
+                return Err("logprobs must be set to true if top_logprobs is used".into());
+            }
+        }
+        if let Some(Some(Stop::Many(stop))) = &self.stop {
+            if stop.len() > 4 {
+                return Err(format!(
+                    "stop must contain at most 4 sequences, got {}",
+                    stop.len()
+                ));
+            }
+        }
+        if let Some(Some(logit_bias)) = &self.logit_bias {
+            if let Some((token, bias)) = logit_bias
+                .iter()
+                .find(|(_, bias)| !(-100..=100).contains(*bias))
+            {
+                return Err(format!(
+                    "logit_bias for token {} must be between -100 and 100, got {}",
+                    token, bias
+                ));
+            }
+        }
+        check_range("frequency_penalty", self.frequency_penalty, -2.0..=2.0)?;
+        check_range("presence_penalty", self.presence_penalty, -2.0..=2.0)?;
+        check_range("temperature", self.temperature, 0.0..=2.0)?;
+        check_range("top_p", self.top_p, 0.0..=1.0)?;
+        // both default to 1
+        let altered = |v: Option<Option<f32>>| matches!(v, Some(Some(v)) if v != 1.0);
+        if altered(self.temperature) && altered(self.top_p) {
+            let msg = "both temperature and top_p are altered, alter one of them but not both";
+            if self.strict_sampling == Some(true) {
+                return Err(msg.into());
+            }
+            warn!("{}", msg);
+        }
+        Ok(())
+    }
+}
+
+impl ChatCompleteModel {
+    /// The maximum number of tokens of the prompt and the completion combined. For `Other` models, the conservative
+    /// 4096 tokens.
+    pub fn context_window(&self) -> usize {
+        match self {
+            Self::Gpt3Turbo => 16_385,
+            Self::Gpt3TurboInstruct => 4_096,
+            Self::Gpt4Turbo
+            | Self::Gpt4TurboLatest
+            | Self::Gpt4TurboVision
+            | Self::Gpt4o
+            | Self::Gpt4oMini => 128_000,
+            Self::O1 | Self::O3 => 200_000,
+            Self::DeepSeekCoder => 64_000,
+            Self::Other(_) => 4_096,
+        }
+    }
+
+    /// Whether the model accepts image content parts. False for `Other` models, as the capability is unknown.
+    pub fn supports_vision(&self) -> bool {
+        matches!(
+            self,
+            Self::Gpt4TurboLatest
+                | Self::Gpt4TurboVision
+                | Self::Gpt4o
+                | Self::Gpt4oMini
+                | Self::O1
+                | Self::O3
+        )
+    }
+
+    /// Whether the model supports tools (function calling). False for `Other` models, as the capability is unknown.
+    pub fn supports_tools(&self) -> bool {
+        matches!(
+            self,
+            Self::Gpt3Turbo
+                | Self::Gpt4Turbo
+                | Self::Gpt4TurboLatest
+                | Self::Gpt4o
+                | Self::Gpt4oMini
+                | Self::O1
+                | Self::O3
+                | Self::DeepSeekCoder
+        )
+    }
+}
+
+impl ChatCompletionMessage {
+    pub fn new_system(content: impl Into<String>, name: &str) -> ChatCompletionMessage {
+        ChatCompletionMessage::System(SystemMessage {
+            content: content.into(),
+            name: Self::get_name(name),
+        })
+    }
+
+    pub fn new_user(content: impl Into<String>, name: &str) -> ChatCompletionMessage {
+        ChatCompletionMessage::User(UserMessage {
+            content: MessageContent::Text(content.into()),
+            name: Self::get_name(name),
+        })
+    }
+
+    pub fn new_user_with_images(
+        content: impl Into<String>,
+        urls: impl IntoIterator<Item = impl Into<String>>,
+        name: &str,
+    ) -> ChatCompletionMessage {
+        let parts = std::iter::once(ContentPart::text(content))
+            .chain(urls.into_iter().map(ContentPart::image_url))
+            .collect();
+        Self::new_user_with_parts(parts, name)
+    }
+
+    pub fn new_user_with_parts(parts: Vec<ContentPart>, name: &str) -> ChatCompletionMessage {
+        ChatCompletionMessage::User(UserMessage {
+            content: MessageContent::Parts(parts),
+            name: Self::get_name(name),
+        })
+    }
+
+    /// An assistant message, e.g. a previous reply to keep in the conversation history. Empty content is omitted,
+    /// which is the case for replies with tool calls only.
+    pub fn new_assistant(
+        content: impl Into<String>,
+        tool_calls: Vec<ToolCall>,
+    ) -> ChatCompletionMessage {
+        let content: String = content.into();
+        ChatCompletionMessage::Assistant(AssistantMessage {
+            content: (!content.is_empty()).then_some(content),
+            tool_calls,
+            ..Default::default()
+        })
+    }
+
+    /// The result of a tool call, to be sent back to the model after the assistant message with the tool calls.
+    pub fn new_tool(
+        content: impl Into<String>,
+        tool_call_id: impl Into<String>,
+    ) -> ChatCompletionMessage {
+        ChatCompletionMessage::Tool(ToolMessage::new(content, tool_call_id))
+    }
+
+    fn get_name(name: &str) -> Option<String> {
+        if name.is_empty() {
+            None
+        } else {
+            Some(name.into())
+        }
+    }
+}
+
+impl AssistantMessage {
+    /// An assistant message carrying the tool calls generated by the model.
+    pub fn with_tool_calls(tool_calls: Vec<ToolCall>) -> Self {
+        Self {
+            tool_calls,
+            ..Default::default()
+        }
+    }
+
+    /// The URL citations among the annotations.
+    pub fn url_citations(&self) -> impl Iterator<Item = &UrlCitation> {
+        self.annotations
+            .iter()
+            .filter_map(|annotation| match annotation {
+                Annotation::UrlCitation { url_citation } => Some(url_citation),
+                Annotation::Unknown => None,
+            })
+    }
+}
+
+impl SystemMessage {
+    /// The contents of the system message.
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// The name of the participant, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+}
+
+impl UserMessage {
+    /// The contents of the user message.
+    pub fn content(&self) -> &MessageContent {
+        &self.content
+    }
+
+    /// The name of the participant, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+}
+
+impl ToolMessage {
+    pub fn new(content: impl Into<String>, tool_call_id: impl Into<String>) -> Self {
+        Self {
+            content: content.into(),
+            tool_call_id: tool_call_id.into(),
+        }
+    }
+
+    /// The contents of the tool message.
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// Tool call that this message is responding to.
+    pub fn tool_call_id(&self) -> &str {
+        &self.tool_call_id
+    }
+}
+
+impl From<ToolMessage> for ChatCompletionMessage {
+    fn from(message: ToolMessage) -> Self {
+        ChatCompletionMessage::Tool(message)
+    }
+}
+
+impl From<AssistantMessage> for ChatCompletionMessage {
+    fn from(message: AssistantMessage) -> Self {
+        ChatCompletionMessage::Assistant(message)
+    }
+}
+
+impl ChatResponseFormatObject {
+    pub fn new(r#type: ChatResponseFormat) -> Self {
+        Self {
+            r#type,
+            json_schema: None,
+        }
+    }
+
+    /// Structured Outputs: the model output will match the json schema of `T`.
+    pub fn json_schema<T: ToSchema>(name: impl Into<String>, strict: bool) -> Self {
+        Self {
+            r#type: ChatResponseFormat::JsonSchema,
+            json_schema: Some(JsonSchemaFormat {
+                name: name.into(),
+                description: None,
+                schema: T::to_schema(),
+                strict: Some(strict),
+            }),
+        }
+    }
+}
+
+impl From<ChatResponseFormat> for ChatResponseFormatObject {
+    fn from(r#type: ChatResponseFormat) -> Self {
+        Self::new(r#type)
+    }
+}
+
+impl ContentPart {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self::Text { text: text.into() }
+    }
+
+    pub fn image_url(url: impl Into<String>) -> Self {
+        Self::ImageUrl {
+            image_url: ImageUrl {
+                url: url.into(),
+                detail: None,
+            },
+        }
+    }
+
+    pub fn image_url_with_detail(url: impl Into<String>, detail: ImageDetail) -> Self {
+        Self::ImageUrl {
+            image_url: ImageUrl {
+                url: url.into(),
+                detail: Some(detail),
+            },
+        }
+    }
+
+    /// Build an image part from raw image bytes, e.g. a local file, encoded as a `data:` URL.
+    pub fn image_base64(data: &[u8], mime: &str, detail: ImageDetail) -> Self {
+        let url = format!("data:{};base64,{}", mime, STANDARD.encode(data));
+        Self::image_url_with_detail(url, detail)
+    }
+}
+
+impl From<String> for MessageContent {
+    fn from(s: String) -> Self {
+        Self::Text(s)
+    }
+}
+
+impl From<&str> for MessageContent {
+    fn from(s: &str) -> Self {
+        Self::Text(s.to_owned())
+    }
+}
+
+impl From<Vec<ContentPart>> for MessageContent {
+    fn from(parts: Vec<ContentPart>) -> Self {
+        Self::Parts(parts)
+    }
+}
+
+impl Tool {
+    /// A function tool with the parameters schema of `T`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name doesn't match `^[a-zA-Z0-9_-]{1,64}$`. See `try_new_function` for names from user input.
+    pub fn new_function<T: ToSchema>(
+        name: impl Into<String>,
+        description: impl Into<String>,
+    ) -> Self {
+        match Self::try_new_function::<T>(name, description) {
+            Ok(tool) => tool,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// A function tool with the parameters schema of `T`, or `LlmError::Validation` if the name doesn't match
+    /// `^[a-zA-Z0-9_-]{1,64}$`.
+    pub fn try_new_function<T: ToSchema>(
+        name: impl Into<String>,
+        description: impl Into<String>,
+    ) -> Result<Self, LlmError> {
+        let name = name.into();
+        validate_function_name(&name).map_err(LlmError::Validation)?;
+        Ok(Self {
+            r#type: ToolType::Function,
+            function: FunctionInfo {
+                name,
+                description: description.into(),
+                parameters: T::to_schema(),
+            },
+        })
+    }
+}
+
+fn check_range(
+    name: &str,
+    value: Option<Option<f32>>,
+    range: RangeInclusive<f32>,
+) -> Result<(), String> {
+    match value {
+        Some(Some(v)) if !range.contains(&v) => Err(format!(
+            "{} must be between {} and {}, got {}",
+            name,
+            range.start(),
+            range.end(),
+            v
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Function names must be a-z, A-Z, 0-9, or contain underscores and dashes, with a maximum length of 64.
+fn validate_function_name(name: &str) -> Result<(), String> {
+    let valid = (1..=64).contains(&name.len())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "invalid function name {:?}: must match ^[a-zA-Z0-9_-]{{1,64}}$",
+            name
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ToSchema, SDK};
+    use anyhow::Result;
+    use schemars::JsonSchema;
+    use strum::IntoEnumIterator;
+
+    #[allow(dead_code)]
+    #[derive(Debug, Clone, Deserialize, JsonSchema)]
+    struct GetWeatherArgs {
+        /// The city to get the weather for.
+        pub city: String,
+        /// the unit
+        pub unit: TemperatureUnit,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+    enum TemperatureUnit {
+        /// Celsius
+        #[default]
+        Celsius,
+        /// Fahrenheit
+        Fahrenheit,
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    struct GetWeatherResponse {
+        temperature: f32,
+        unit: TemperatureUnit,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Debug, Deserialize, JsonSchema)]
+    struct ExplainMoodArgs {
+        /// The mood to explain.
+        pub name: String,
+    }
+
+    fn get_weather_forecast(args: GetWeatherArgs) -> GetWeatherResponse {
+        match args.unit {
+            TemperatureUnit::Celsius => GetWeatherResponse {
+                temperature: 22.2,
+                unit: TemperatureUnit::Celsius,
+            },
+            TemperatureUnit::Fahrenheit => GetWeatherResponse {
+                temperature: 72.0,
+                unit: TemperatureUnit::Fahrenheit,
+            },
+        }
+    }
+
+    #[test]
+    fn chat_completion_request_tool_choice_function_serialize_should_work() {
+        let req = ChatCompletionRequestBuilder::default()
+            .tool_choice(ToolChoice::Function {
+                name: "my_function".to_string(),
+            })
+            .messages(vec![ChatCompletionMessage::new_user("Hi", "")])
             .build()
             .unwrap();
         let json = serde_json::to_value(req).unwrap();
         assert_eq!(
-            json,
+            json,
+            serde_json::json!({
+              "tool_choice": {
+                "type": "function",
+                "function": {
+                  "name": "my_function"
+                }
+              },
+              "model": "gpt-4o-mini",
+              "messages": [{ "role": "user", "content": "Hi" }]
+            })
+        );
+    }
+
+    #[test]
+    fn chat_completion_request_should_round_trip_through_serde() {
+        let mut req = get_tool_completion_request();
+        req.tool_choice = Some(ToolChoice::Function {
+            name: "get_weather_forecast".into(),
+        });
+        req.stop = Some(Stop::Many(vec!["\n".into()]));
+        req.response_format = Some(ChatResponseFormat::Json.into());
+        req.stream_options = Some(StreamOptions {
+            include_usage: true,
+        });
+        req.messages
+            .push(ChatCompletionMessage::new_user_with_images(
+                "What is in the image?",
+                ["https://example.com/image.png"],
+                "",
+            ));
+        let json = serde_json::to_value(&req).unwrap();
+        let replayed: ChatCompletionRequest = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(replayed).unwrap(), json);
+
+        let req: ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+          "model": "gpt-4o",
+          "messages": [{ "role": "user", "content": "Hi" }],
+          "tool_choice": "required"
+        }))
+        .unwrap();
+        assert_eq!(req.model, ChatCompleteModel::Gpt4o);
+        assert_eq!(req.tool_choice, Some(ToolChoice::Required));
+    }
+
+    #[test]
+    fn chat_completion_request_getters_should_work() {
+        let req = ChatCompletionRequestBuilder::default()
+            .model(ChatCompleteModel::Gpt4o)
+            .messages(vec![
+                ChatCompletionMessage::new_system("Be brief.", ""),
+                ChatCompletionMessage::new_user("Hi", "user1"),
+            ])
+            .temperature(0.5)
+            .user("user1")
+            .build()
+            .unwrap();
+        assert_eq!(req.model(), &ChatCompleteModel::Gpt4o);
+        assert_eq!(req.messages().len(), 2);
+        assert_eq!(req.temperature(), Some(0.5));
+        assert_eq!(req.top_p(), None);
+        assert_eq!(req.user(), Some("user1"));
+        assert!(req.tools().is_empty());
+        match &req.messages()[1] {
+            ChatCompletionMessage::User(m) => {
+                assert_eq!(m.content(), &MessageContent::Text("Hi".into()));
+                assert_eq!(m.name(), Some("user1"));
+            }
+            m => panic!("unexpected message: {:?}", m),
+        }
+    }
+
+    #[test]
+    fn user_hashed_should_work() {
+        let req = ChatCompletionRequestBuilder::default()
+            .messages(vec![ChatCompletionMessage::new_user("Hi", "")])
+            .user_hashed("user-42", "")
+            .build()
+            .unwrap();
+        // sha256("user-42")
+        assert_eq!(
+            req.user(),
+            Some("6d894aa3ee802549d7f340e7c1cf0d1c1cb14cd84f768d92ffaa6785337c4997")
+        );
+
+        let salted = ChatCompletionRequestBuilder::default()
+            .messages(vec![ChatCompletionMessage::new_user("Hi", "")])
+            .user_hashed("user-42", "pepper")
+            .build()
+            .unwrap();
+        // sha256("pepperuser-42")
+        assert_eq!(
+            salted.user(),
+            Some("78126829e9d59f54da027fdc286e75a02736dd09d28b8c2a84190f0277705850")
+        );
+    }
+
+    #[test]
+    fn tool_choice_serialize_should_work() {
+        for (choice, expected) in [
+            (ToolChoice::None, "none"),
+            (ToolChoice::Auto, "auto"),
+            (ToolChoice::Required, "required"),
+        ] {
+            assert_eq!(serde_json::to_value(choice).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn chat_completion_request_serialize_should_work() {
+        let mut req = get_simple_completion_request();
+        req.tool_choice = Some(ToolChoice::Auto);
+        let json = serde_json::to_value(req).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+              "tool_choice": "auto",
+              "model": "gpt-3.5-turbo-1106",
+              "messages": [{
+                "role": "system",
+                "content": "I can answer any question you ask me."
+              }, {
+                "role": "user",
+                "content": "What is human life expectancy in the world?",
+                "name": "user1"
+              }]
+            })
+        );
+    }
+
+    #[test]
+    fn chat_completion_request_with_tools_serialize_should_work() {
+        let req = get_tool_completion_request();
+        let json = serde_json::to_value(req).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+              "model": "gpt-3.5-turbo-1106",
+              "messages": [{
+                "role": "system",
+                "content": "I can choose the right function for you."
+              }, {
+                "role": "user",
+                "content": "What is the weather like in Boston?",
+                "name": "user1"
+              }],
+              "tools": [
+                {
+                  "type": "function",
+                  "function": {
+                    "description": "Get the weather forecast for a city.",
+                    "name": "get_weather_forecast",
+                    "parameters": GetWeatherArgs::to_schema()
+                  }
+                },
+                {
+                  "type": "function",
+                  "function": {
+                    "description": "Explain the meaning of the given mood.",
+                    "name": "explain_mood",
+                    "parameters": ExplainMoodArgs::to_schema()
+                  }
+                }
+              ]
+            })
+        );
+    }
+
+    #[test]
+    fn chat_completion_request_stream_options_serialize_should_work() {
+        let req = ChatCompletionRequestBuilder::default()
+            .model(ChatCompleteModel::Gpt3Turbo)
+            .messages(vec![ChatCompletionMessage::new_user("Hi", "")])
+            .stream(true)
+            .stream_options(StreamOptions {
+                include_usage: true,
+            })
+            .build()
+            .unwrap();
+        let json = serde_json::to_value(req).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+              "model": "gpt-3.5-turbo-1106",
+              "messages": [{
+                "role": "user",
+                "content": "Hi"
+              }],
+              "stream": true,
+              "stream_options": {
+                "include_usage": true
+              }
+            })
+        );
+    }
+
+    #[test]
+    fn chat_completion_request_with_images_serialize_should_work() {
+        let messages = vec![ChatCompletionMessage::new_user_with_images(
+            "What's in this image?",
+            ["https://example.com/cat.png"],
+            "",
+        )];
+        let req = ChatCompletionRequest::new(ChatCompleteModel::Gpt4TurboVision, messages);
+        let json = serde_json::to_value(req).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+              "model": "gpt-4-1106-vision-preview",
+              "messages": [{
+                "role": "user",
+                "content": [{
+                  "type": "text",
+                  "text": "What's in this image?"
+                }, {
+                  "type": "image_url",
+                  "image_url": {
+                    "url": "https://example.com/cat.png"
+                  }
+                }]
+              }]
+            })
+        );
+    }
+
+    #[test]
+    fn content_part_image_base64_should_work() {
+        let part = ContentPart::image_base64(b"hello", "image/png", ImageDetail::Low);
+        assert_eq!(
+            serde_json::to_value(part).unwrap(),
             serde_json::json!({
-              "tool_choice": {
-                "type": "function",
-                "function": {
-                  "name": "my_function"
-                }
-              },
-              "messages": []
+              "type": "image_url",
+              "image_url": {
+                "url": "data:image/png;base64,aGVsbG8=",
+                "detail": "low"
+              }
             })
         );
     }
 
     #[test]
-    fn chat_completion_request_serialize_should_work() {
-        let mut req = get_simple_completion_request();
-        req.tool_choice = Some(ToolChoice::Auto);
+    fn chat_completion_request_logit_bias_serialize_should_work() {
+        let req = ChatCompletionRequestBuilder::default()
+            .model(ChatCompleteModel::Gpt3Turbo)
+            .messages(vec![ChatCompletionMessage::new_user("Hi", "")])
+            .logit_bias(HashMap::from([(50256, -100)]))
+            .build()
+            .unwrap();
         let json = serde_json::to_value(req).unwrap();
+        assert_eq!(json["logit_bias"], serde_json::json!({ "50256": -100 }));
+    }
+
+    #[test]
+    fn chat_completion_request_logit_bias_out_of_range_should_fail() {
+        let ret = ChatCompletionRequestBuilder::default()
+            .messages(vec![ChatCompletionMessage::new_user("Hi", "")])
+            .logit_bias(HashMap::from([(50256, 101)]))
+            .build();
+        assert!(matches!(
+            ret,
+            Err(ChatCompletionRequestBuilderError::ValidationError(_))
+        ));
+    }
+
+    #[test]
+    fn chat_completion_request_extra_should_be_merged() {
+        let req = ChatCompletionRequestBuilder::default()
+            .messages(vec![ChatCompletionMessage::new_user("Hi", "")])
+            .extra_param("top_k", 40)
+            .extra_param("provider", serde_json::json!({ "order": ["groq"] }))
+            .build()
+            .unwrap();
+        let json = serde_json::to_value(&req).unwrap();
         assert_eq!(
             json,
             serde_json::json!({
-              "tool_choice": "auto",
-              "model": "gpt-3.5-turbo-1106",
-              "messages": [{
-                "role": "system",
-                "content": "I can answer any question you ask me."
-              }, {
-                "role": "user",
-                "content": "What is human life expectancy in the world?",
-                "name": "user1"
-              }]
+              "model": "gpt-4o-mini",
+              "messages": [{ "role": "user", "content": "Hi" }],
+              "top_k": 40,
+              "provider": { "order": ["groq"] }
             })
         );
+        assert_eq!(req.extra().unwrap()["top_k"], 40);
     }
 
     #[test]
-    fn chat_completion_request_with_tools_serialize_should_work() {
-        let req = get_tool_completion_request();
+    fn chat_completion_request_deterministic_should_work() {
+        let req = ChatCompletionRequestBuilder::default()
+            .messages(vec![ChatCompletionMessage::new_user("Hi", "")])
+            .deterministic(42)
+            .build()
+            .unwrap();
+        let json = serde_json::to_value(req).unwrap();
+        assert_eq!(json["temperature"], 0.0);
+        assert_eq!(json["top_p"], 1.0);
+        assert_eq!(json["seed"], 42);
+    }
+
+    #[test]
+    fn chat_completion_request_temperature_with_top_p_should_be_checked() {
+        let mut builder = ChatCompletionRequestBuilder::default();
+        builder
+            .messages(vec![ChatCompletionMessage::new_user("Hi", "")])
+            .temperature(0.5)
+            .top_p(0.9);
+        // a warning by default
+        assert!(builder.build().is_ok());
+        let ret = builder.strict_sampling(true).build();
+        assert!(matches!(
+            ret,
+            Err(ChatCompletionRequestBuilderError::ValidationError(_))
+        ));
+        // top_p of 1 is the default
+        assert!(builder.top_p(1.0).build().is_ok());
+    }
+
+    #[test]
+    fn chat_completion_request_empty_messages_should_fail() {
+        let ret = ChatCompletionRequestBuilder::default()
+            .messages(vec![])
+            .build();
+        assert!(matches!(
+            ret,
+            Err(ChatCompletionRequestBuilderError::ValidationError(_))
+        ));
+        let ret = ChatCompletionRequest::try_new(ChatCompleteModel::Gpt4oMini, vec![]);
+        assert!(matches!(ret, Err(LlmError::Validation(_))));
+    }
+
+    #[test]
+    fn chat_completion_request_stop_serialize_should_work() {
+        let req = ChatCompletionRequestBuilder::default()
+            .messages(vec![ChatCompletionMessage::new_user("Hi", "")])
+            .stop_sequence("\n")
+            .build()
+            .unwrap();
+        let json = serde_json::to_value(req).unwrap();
+        assert_eq!(json["stop"], serde_json::json!("\n"));
+
+        let req = ChatCompletionRequestBuilder::default()
+            .messages(vec![ChatCompletionMessage::new_user("Hi", "")])
+            .stop_sequences(["END", "STOP"])
+            .build()
+            .unwrap();
+        let json = serde_json::to_value(req).unwrap();
+        assert_eq!(json["stop"], serde_json::json!(["END", "STOP"]));
+    }
+
+    #[test]
+    fn chat_completion_request_too_many_stop_sequences_should_fail() {
+        let ret = ChatCompletionRequestBuilder::default()
+            .messages(vec![ChatCompletionMessage::new_user("Hi", "")])
+            .stop_sequences(["a", "b", "c", "d", "e"])
+            .build();
+        assert!(matches!(
+            ret,
+            Err(ChatCompletionRequestBuilderError::ValidationError(_))
+        ));
+    }
+
+    #[test]
+    fn chat_completion_request_sampling_ranges_should_be_validated() {
+        let build = |f: &dyn Fn(&mut ChatCompletionRequestBuilder)| {
+            let mut builder = ChatCompletionRequestBuilder::default();
+            builder.messages(vec![ChatCompletionMessage::new_user("Hi", "")]);
+            f(&mut builder);
+            builder.build()
+        };
+        assert!(build(&|b| {
+            b.frequency_penalty(-2.0)
+                .presence_penalty(2.0)
+                .temperature(0.0)
+                .top_p(1.0);
+        })
+        .is_ok());
+        for ret in [
+            build(&|b| {
+                b.frequency_penalty(-2.1);
+            }),
+            build(&|b| {
+                b.presence_penalty(2.5);
+            }),
+            build(&|b| {
+                b.temperature(2.1);
+            }),
+            build(&|b| {
+                b.top_p(-0.1);
+            }),
+        ] {
+            assert!(matches!(
+                ret,
+                Err(ChatCompletionRequestBuilderError::ValidationError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn chat_completion_request_parallel_tool_calls_serialize_should_work() {
+        let req = ChatCompletionRequestBuilder::default()
+            .messages(vec![ChatCompletionMessage::new_user("Hi", "")])
+            .parallel_tool_calls(false)
+            .build()
+            .unwrap();
         let json = serde_json::to_value(req).unwrap();
+        assert_eq!(json["parallel_tool_calls"], serde_json::json!(false));
+    }
+
+    #[test]
+    fn function_name_should_be_validated() {
+        assert!(Tool::try_new_function::<GetWeatherArgs>("get-weather_2", "").is_ok());
+        let long = "a".repeat(65);
+        for name in ["", "get weather", "天气", long.as_str()] {
+            let ret = Tool::try_new_function::<GetWeatherArgs>(name, "");
+            assert!(matches!(ret, Err(LlmError::Validation(_))), "{:?}", name);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid function name")]
+    fn new_function_should_panic_on_invalid_name() {
+        Tool::new_function::<GetWeatherArgs>("get.weather", "");
+    }
+
+    #[test]
+    fn tool_messages_serialize_should_work() {
+        let tool_call = ToolCall {
+            id: "call_abc123".into(),
+            r#type: ToolType::Function,
+            function: FunctionCall {
+                name: "get_weather_forecast".into(),
+                arguments: r#"{"city":"Boston"}"#.into(),
+            },
+        };
+        let messages = vec![
+            AssistantMessage::with_tool_calls(vec![tool_call]).into(),
+            ChatCompletionMessage::new_tool(r#"{"temperature":22.2}"#, "call_abc123"),
+        ];
         assert_eq!(
-            json,
-            serde_json::json!({
-              "model": "gpt-3.5-turbo-1106",
-              "messages": [{
-                "role": "system",
-                "content": "I can choose the right function for you."
-              }, {
-                "role": "user",
-                "content": "What is the weather like in Boston?",
-                "name": "user1"
-              }],
-              "tools": [
-                {
+            serde_json::to_value(messages).unwrap(),
+            serde_json::json!([
+              {
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [{
+                  "id": "call_abc123",
                   "type": "function",
-                  "function": {
-                    "description": "Get the weather forecast for a city.",
-                    "name": "get_weather_forecast",
-                    "parameters": GetWeatherArgs::to_schema()
-                  }
-                },
+                  "function": { "name": "get_weather_forecast", "arguments": "{\"city\":\"Boston\"}" }
+                }]
+              },
+              { "role": "tool", "content": "{\"temperature\":22.2}", "tool_call_id": "call_abc123" }
+            ])
+        );
+    }
+
+    #[test]
+    fn response_annotations_should_deserialize() {
+        let res: ChatCompletionResponse = serde_json::from_value(serde_json::json!({
+          "id": "chatcmpl-123",
+          "object": "chat.completion",
+          "created": 1702685778,
+          "model": "gpt-4o-search-preview",
+          "choices": [{
+            "index": 0,
+            "message": {
+              "role": "assistant",
+              "content": "Rust 1.80 was released in July 2024 (blog.rust-lang.org).",
+              "annotations": [
                 {
-                  "type": "function",
-                  "function": {
-                    "description": "Explain the meaning of the given mood.",
-                    "name": "explain_mood",
-                    "parameters": ExplainMoodArgs::to_schema()
+                  "type": "url_citation",
+                  "url_citation": {
+                    "start_index": 37,
+                    "end_index": 57,
+                    "title": "Announcing Rust 1.80.0",
+                    "url": "https://blog.rust-lang.org/2024/07/25/Rust-1.80.0.html"
                   }
-                }
+                },
+                { "type": "file_citation", "file_id": "file-123" }
               ]
+            },
+            "finish_reason": "stop"
+          }],
+          "usage": { "prompt_tokens": 9, "completion_tokens": 16, "total_tokens": 25 }
+        }))
+        .unwrap();
+        let message = &res.choices[0].message;
+        assert_eq!(message.annotations.len(), 2);
+        assert_eq!(message.annotations[1], Annotation::Unknown);
+        let citations: Vec<_> = message.url_citations().collect();
+        assert_eq!(citations.len(), 1);
+        assert_eq!(citations[0].title, "Announcing Rust 1.80.0");
+        assert_eq!((citations[0].start_index, citations[0].end_index), (37, 57));
+
+        // annotations are not sent back
+        let json = serde_json::to_value(ChatCompletionMessage::from(message.clone())).unwrap();
+        assert!(json.get("annotations").is_none());
+    }
+
+    #[test]
+    fn response_message_should_round_trip_into_request() {
+        let res: ChatCompletionResponse = serde_json::from_value(serde_json::json!({
+          "id": "chatcmpl-123",
+          "object": "chat.completion",
+          "created": 1702685778,
+          "model": "gpt-4o-mini",
+          "system_fingerprint": null,
+          "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": "Hello! How can I help?" },
+            "finish_reason": "stop"
+          }],
+          "usage": { "prompt_tokens": 9, "completion_tokens": 6, "total_tokens": 15 }
+        }))
+        .unwrap();
+        let messages: Vec<ChatCompletionMessage> = vec![
+            ChatCompletionMessage::new_user("Hi", ""),
+            res.choices[0].message.clone().into(),
+            ChatCompletionMessage::new_assistant("", vec![]),
+        ];
+        let json = serde_json::to_string(&messages).unwrap();
+        assert_eq!(
+            json,
+            r#"[{"role":"user","content":"Hi"},{"role":"assistant","content":"Hello! How can I help?"},{"role":"assistant","content":null}]"#
+        );
+        let messages: Vec<ChatCompletionMessage> = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&messages).unwrap(), json);
+        assert!(matches!(
+            &messages[1],
+            ChatCompletionMessage::Assistant(m) if m.content.as_deref() == Some("Hello! How can I help?")
+        ));
+    }
+
+    #[test]
+    fn chat_completion_request_store_serialize_should_work() {
+        let req = ChatCompletionRequestBuilder::default()
+            .messages(vec![ChatCompletionMessage::new_user("Hi", "")])
+            .store(true)
+            .metadata(HashMap::from([("env".to_string(), "prod".to_string())]))
+            .build()
+            .unwrap();
+        let json = serde_json::to_value(req).unwrap();
+        assert_eq!(json["store"], true);
+        assert_eq!(json["metadata"], serde_json::json!({ "env": "prod" }));
+
+        let req = ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4oMini,
+            vec![ChatCompletionMessage::new_user("Hi", "")],
+        );
+        let json = serde_json::to_value(req).unwrap();
+        assert!(json.get("store").is_none());
+        assert!(json.get("metadata").is_none());
+    }
+
+    #[test]
+    fn chat_complete_model_metadata_should_work() {
+        assert_eq!(ChatCompleteModel::Gpt4oMini.context_window(), 128_000);
+        assert_eq!(ChatCompleteModel::Gpt3Turbo.context_window(), 16_385);
+        assert!(ChatCompleteModel::Gpt4o.supports_vision());
+        assert!(!ChatCompleteModel::Gpt3Turbo.supports_vision());
+        assert!(ChatCompleteModel::Gpt3Turbo.supports_tools());
+        assert!(!ChatCompleteModel::Gpt4TurboVision.supports_tools());
+
+        let other = ChatCompleteModel::Other("llama3".into());
+        assert_eq!(other.context_window(), 4_096);
+        assert!(!other.supports_vision());
+        assert!(!other.supports_tools());
+    }
+
+    #[test]
+    fn chat_completion_service_tier_should_work() {
+        let req = ChatCompletionRequestBuilder::default()
+            .messages(vec![ChatCompletionMessage::new_user("Hi", "")])
+            .service_tier(ServiceTier::Flex)
+            .build()
+            .unwrap();
+        let json = serde_json::to_value(req).unwrap();
+        assert_eq!(json["service_tier"], "flex");
+
+        let res: ChatCompletionResponse = serde_json::from_value(serde_json::json!({
+          "id": "chatcmpl-123",
+          "object": "chat.completion",
+          "created": 1702685778,
+          "model": "o3",
+          "system_fingerprint": null,
+          "choices": [],
+          "usage": { "prompt_tokens": 9, "completion_tokens": 0, "total_tokens": 9 },
+          "service_tier": "flex"
+        }))
+        .unwrap();
+        assert_eq!(res.service_tier, Some(ServiceTier::Flex));
+
+        let res: ChatCompletionResponse = serde_json::from_value(serde_json::json!({
+          "id": "chatcmpl-123",
+          "object": "chat.completion",
+          "created": 1702685778,
+          "model": "o3",
+          "system_fingerprint": null,
+          "choices": [],
+          "usage": { "prompt_tokens": 9, "completion_tokens": 0, "total_tokens": 9 },
+          "service_tier": "batch"
+        }))
+        .unwrap();
+        assert_eq!(res.service_tier, Some(ServiceTier::Unknown));
+    }
+
+    #[test]
+    fn finish_reason_should_tolerate_unknown_values() {
+        let reasons: Vec<FinishReason> = serde_json::from_value(serde_json::json!([
+            "stop",
+            "function_call",
+            "insufficient_system_resource"
+        ]))
+        .unwrap();
+        assert_eq!(
+            reasons,
+            [
+                FinishReason::Stop,
+                FinishReason::FunctionCall,
+                FinishReason::Unknown
+            ]
+        );
+    }
+
+    #[test]
+    fn chat_completion_choice_tool_calls_should_work() {
+        let choice: ChatCompletionChoice = serde_json::from_value(serde_json::json!({
+          "index": 0,
+          "finish_reason": "tool_calls",
+          "message": {
+            "role": "assistant",
+            "content": null,
+            "tool_calls": [{
+              "id": "call_1",
+              "type": "function",
+              "function": { "name": "get_weather", "arguments": "{}" }
+            }]
+          }
+        }))
+        .unwrap();
+        assert!(choice.wants_tool_call());
+        assert_eq!(choice.tool_calls()[0].function.name, "get_weather");
+
+        let choice: ChatCompletionChoice = serde_json::from_value(serde_json::json!({
+          "index": 0,
+          "finish_reason": "stop",
+          "message": { "role": "assistant", "content": "Sunny." }
+        }))
+        .unwrap();
+        assert!(!choice.wants_tool_call());
+        assert!(choice.tool_calls().is_empty());
+    }
+
+    #[test]
+    fn chat_completion_request_overrides_should_work() {
+        let base = ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4oMini,
+            vec![ChatCompletionMessage::new_user("Hi", "")],
+        );
+        let req = base
+            .clone()
+            .with_model(ChatCompleteModel::Gpt4o)
+            .with_temperature(0.2)
+            .with_timeout(Duration::from_secs(5));
+        assert_eq!(req.model(), &ChatCompleteModel::Gpt4o);
+        assert_eq!(req.temperature(), Some(0.2));
+        assert_eq!(IntoRequest::timeout(&req), Some(Duration::from_secs(5)));
+        assert_eq!(IntoRequest::timeout(&base), None);
+
+        let json = serde_json::to_value(req).unwrap();
+        assert_eq!(json["model"], "gpt-4o");
+        assert!(json.get("timeout").is_none());
+    }
+
+    #[test]
+    fn chat_completion_request_with_prefix_should_work() {
+        let prefix = vec![ChatCompletionMessage::new_system(
+            "A long static context.",
+            "",
+        )];
+        let req = ChatCompletionRequest::new_with_prefix(
+            ChatCompleteModel::Gpt4oMini,
+            prefix,
+            [ChatCompletionMessage::new_user("Question", "")],
+        );
+        let json = serde_json::to_value(req).unwrap();
+        assert_eq!(json["messages"][0]["role"], "system");
+        assert_eq!(json["messages"][1]["content"], "Question");
+
+        let usage: ChatCompleteUsage = serde_json::from_value(serde_json::json!({
+          "prompt_tokens": 9, "completion_tokens": 3, "total_tokens": 12
+        }))
+        .unwrap();
+        assert_eq!(usage.cached_tokens(), 0);
+    }
+
+    #[test]
+    fn chat_completion_request_max_completion_tokens_serialize_should_work() {
+        let req = ChatCompletionRequestBuilder::default()
+            .model(ChatCompleteModel::O1)
+            .messages(vec![ChatCompletionMessage::new_user("Hi", "")])
+            .max_completion_tokens(1024usize)
+            .build()
+            .unwrap();
+        let json = serde_json::to_value(req).unwrap();
+        assert_eq!(json["model"], "o1");
+        assert_eq!(json["max_completion_tokens"], 1024);
+        assert!(json.get("max_tokens").is_none());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn created_at_should_work() {
+        let res: ChatCompletionResponse = serde_json::from_value(serde_json::json!({
+          "id": "chatcmpl-123",
+          "object": "chat.completion",
+          "created": 1702685778,
+          "model": "gpt-4o-mini",
+          "system_fingerprint": null,
+          "choices": [],
+          "usage": { "prompt_tokens": 9, "completion_tokens": 0, "total_tokens": 9 }
+        }))
+        .unwrap();
+        assert_eq!(res.created_at().to_rfc3339(), "2023-12-16T00:16:18+00:00");
+    }
+
+    #[cfg(feature = "extra-fields")]
+    #[test]
+    fn unknown_response_fields_should_be_kept() {
+        let res: ChatCompletionResponse = serde_json::from_value(serde_json::json!({
+          "id": "chatcmpl-123",
+          "object": "chat.completion",
+          "created": 1702685778,
+          "model": "deepseek-chat",
+          "system_fingerprint": null,
+          "choices": [],
+          "usage": { "prompt_tokens": 9, "completion_tokens": 0, "total_tokens": 9 },
+          "provider": "local-gateway"
+        }))
+        .unwrap();
+        assert_eq!(res.extra.len(), 1);
+        assert_eq!(res.extra["provider"], "local-gateway");
+    }
+
+    #[test]
+    fn chat_complete_usage_details_deserialize_should_work() {
+        let usage: ChatCompleteUsage = serde_json::from_value(serde_json::json!({
+          "prompt_tokens": 2006,
+          "completion_tokens": 300,
+          "total_tokens": 2306,
+          "prompt_tokens_details": { "cached_tokens": 1920 },
+          "completion_tokens_details": {
+            "reasoning_tokens": 192,
+            "accepted_prediction_tokens": 0,
+            "rejected_prediction_tokens": 0
+          }
+        }))
+        .unwrap();
+        assert_eq!(usage.cached_tokens(), 1920);
+        assert_eq!(usage.reasoning_tokens(), 192);
+        let completion = usage.completion_tokens_details.unwrap();
+        assert_eq!(completion.reasoning_tokens, Some(192));
+        assert_eq!(completion.audio_tokens, None);
+        assert_eq!(
+            usage.prompt_tokens_details.unwrap().cached_tokens,
+            Some(1920)
+        );
+    }
+
+    #[test]
+    fn chat_completion_request_response_format_serialize_should_work() {
+        let req = ChatCompletionRequestBuilder::default()
+            .messages(vec![ChatCompletionMessage::new_user("Hi", "")])
+            .response_format(ChatResponseFormat::Json.into())
+            .build()
+            .unwrap();
+        let json = serde_json::to_value(req).unwrap();
+        assert_eq!(
+            json["response_format"],
+            serde_json::json!({ "type": "json_object" })
+        );
+
+        let req = ChatCompletionRequestBuilder::default()
+            .messages(vec![ChatCompletionMessage::new_user("Hi", "")])
+            .response_format(ChatResponseFormatObject::json_schema::<GetWeatherArgs>(
+                "get_weather_args",
+                true,
+            ))
+            .build()
+            .unwrap();
+        let json = serde_json::to_value(req).unwrap();
+        assert_eq!(
+            json["response_format"],
+            serde_json::json!({
+              "type": "json_schema",
+              "json_schema": {
+                "name": "get_weather_args",
+                "schema": GetWeatherArgs::to_schema(),
+                "strict": true
+              }
+            })
+        );
+    }
+
+    #[test]
+    fn chat_completion_response_with_reasoning_content_deserialize_should_work() {
+        let res: ChatCompletionResponse = serde_json::from_value(serde_json::json!({
+          "id": "930c60df-bf64-41c9-a88e-3ec75f81e00e",
+          "object": "chat.completion",
+          "created": 1705651092,
+          "model": "deepseek-reasoner",
+          "system_fingerprint": null,
+          "choices": [{
+            "index": 0,
+            "message": {
+              "role": "assistant",
+              "content": "9.11 is smaller.",
+              "reasoning_content": "Compare the decimals one by one."
+            },
+            "finish_reason": "stop"
+          }],
+          "usage": { "prompt_tokens": 16, "completion_tokens": 10, "total_tokens": 26 }
+        }))
+        .unwrap();
+        assert_eq!(
+            res.model,
+            ChatCompleteModel::Other("deepseek-reasoner".to_string())
+        );
+        let message = &res.choices[0].message;
+        assert_eq!(message.content.as_deref(), Some("9.11 is smaller."));
+        assert_eq!(
+            message.reasoning_content.as_deref(),
+            Some("Compare the decimals one by one.")
+        );
+    }
+
+    #[test]
+    fn chat_complete_model_other_should_round_trip() {
+        for id in [
+            "llama3:70b",
+            "deepseek-chat",
+            "gpt-4o-2024-08-06",
+            "GPT-4O",
+            "",
+        ] {
+            let model: ChatCompleteModel = serde_json::from_value(id.into()).unwrap();
+            assert_eq!(model, ChatCompleteModel::Other(id.to_string()));
+            assert_eq!(serde_json::to_value(&model).unwrap(), id);
+        }
+        // every known variant (and the default `Other`) deserializes back to itself
+        for model in ChatCompleteModel::iter() {
+            let id = serde_json::to_value(&model).unwrap();
+            assert_eq!(
+                serde_json::from_value::<ChatCompleteModel>(id).unwrap(),
+                model
+            );
+        }
+    }
+
+    #[test]
+    fn chat_complete_model_should_serialize() {
+        for (model, name) in [
+            (ChatCompleteModel::Gpt4o, "gpt-4o"),
+            (ChatCompleteModel::Gpt4oMini, "gpt-4o-mini"),
+            (ChatCompleteModel::O1, "o1"),
+            (ChatCompleteModel::O3, "o3"),
+        ] {
+            assert_eq!(serde_json::to_value(&model).unwrap(), name);
+            assert_eq!(model.to_string(), name);
+            assert_eq!(
+                serde_json::from_value::<ChatCompleteModel>(name.into()).unwrap(),
+                model
+            );
+        }
+        assert_eq!(ChatCompleteModel::default(), ChatCompleteModel::Gpt4oMini);
+        assert_eq!(
+            serde_json::to_value(ChatCompleteModel::Gpt4Turbo).unwrap(),
+            "gpt-4-1106-preview"
+        );
+        assert_eq!(
+            serde_json::to_value(ChatCompleteModel::Gpt4TurboLatest).unwrap(),
+            "gpt-4-turbo"
+        );
+        assert_eq!(
+            serde_json::from_value::<ChatCompleteModel>("gpt-4-turbo".into()).unwrap(),
+            ChatCompleteModel::Gpt4TurboLatest
+        );
+    }
+
+    #[test]
+    fn chat_completion_request_top_logprobs_should_be_validated() {
+        let req = ChatCompletionRequestBuilder::default()
+            .messages(vec![ChatCompletionMessage::new_user("Hi", "")])
+            .logprobs(true)
+            .top_logprobs(2)
+            .build()
+            .unwrap();
+        let json = serde_json::to_value(req).unwrap();
+        assert_eq!(json["logprobs"], serde_json::json!(true));
+        assert_eq!(json["top_logprobs"], serde_json::json!(2));
+
+        let ret = ChatCompletionRequestBuilder::default()
+            .messages(vec![ChatCompletionMessage::new_user("Hi", "")])
+            .logprobs(true)
+            .top_logprobs(21)
+            .build();
+        assert!(ret.is_err());
+
+        let ret = ChatCompletionRequestBuilder::default()
+            .messages(vec![ChatCompletionMessage::new_user("Hi", "")])
+            .top_logprobs(2)
+            .build();
+        assert!(ret.is_err());
+    }
+
+    #[test]
+    fn chat_completion_response_with_logprobs_deserialize_should_work() {
+        let res: ChatCompletionResponse = serde_json::from_value(serde_json::json!({
+          "id": "chatcmpl-123",
+          "object": "chat.completion",
+          "created": 1702685778,
+          "model": "gpt-3.5-turbo-1106",
+          "system_fingerprint": null,
+          "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": "Hello!" },
+            "logprobs": {
+              "content": [{
+                "token": "Hello",
+                "logprob": -0.31725305,
+                "bytes": [72, 101, 108, 108, 111],
+                "top_logprobs": [{
+                  "token": "Hello",
+                  "logprob": -0.31725305,
+                  "bytes": [72, 101, 108, 108, 111]
+                }, {
+                  "token": "Hi",
+                  "logprob": -1.3190403,
+                  "bytes": [72, 105]
+                }]
+              }]
+            },
+            "finish_reason": "stop"
+          }],
+          "usage": { "prompt_tokens": 9, "completion_tokens": 2, "total_tokens": 11 }
+        }))
+        .unwrap();
+        let logprobs = res.choices[0].logprobs.as_ref().unwrap();
+        let content = logprobs.content.as_ref().unwrap();
+        assert_eq!(content[0].token, "Hello");
+        assert_eq!(content[0].bytes.as_deref(), Some(&b"Hello"[..]));
+        assert_eq!(content[0].top_logprobs.len(), 2);
+        assert_eq!(content[0].top_logprobs[1].token, "Hi");
+    }
+
+    #[test]
+    fn chat_completion_response_best_choice_should_work() {
+        let choice = |index: usize, logprob: f32| {
+            serde_json::json!({
+              "index": index,
+              "message": { "role": "assistant", "content": format!("choice {}", index) },
+              "logprobs": { "content": [{ "token": "a", "logprob": logprob, "top_logprobs": [] }] },
+              "finish_reason": "stop"
             })
+        };
+        let mut value = serde_json::json!({
+          "id": "chatcmpl-123",
+          "object": "chat.completion",
+          "created": 1702685778,
+          "model": "gpt-3.5-turbo-1106",
+          "system_fingerprint": null,
+          "choices": [choice(0, -2.0), choice(1, -0.5), choice(2, -1.0)],
+          "usage": { "prompt_tokens": 9, "completion_tokens": 3, "total_tokens": 12 }
+        });
+        let res: ChatCompletionResponse = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(res.best_choice().unwrap().index, 1);
+        assert_eq!(res.choices_by_index().len(), 3);
+        assert_eq!(
+            res.choices_by_index()[&2].message.content.as_deref(),
+            Some("choice 2")
         );
+
+        value["choices"][1]["logprobs"] = serde_json::Value::Null;
+        let res: ChatCompletionResponse = serde_json::from_value(value).unwrap();
+        assert_eq!(res.best_choice().unwrap().index, 0);
+    }
+
+    #[test]
+    fn fingerprint_changed_from_should_work() {
+        let mut value = serde_json::json!({
+          "id": "chatcmpl-123",
+          "object": "chat.completion",
+          "created": 1702685778,
+          "model": "gpt-3.5-turbo-1106",
+          "system_fingerprint": null,
+          "choices": [],
+          "usage": { "prompt_tokens": 9, "completion_tokens": 3, "total_tokens": 12 }
+        });
+        let res: ChatCompletionResponse = serde_json::from_value(value.clone()).unwrap();
+        assert!(!res.fingerprint_changed_from("fp_44709d6fcb"));
+
+        value["system_fingerprint"] = "fp_44709d6fcb".into();
+        let res: ChatCompletionResponse = serde_json::from_value(value).unwrap();
+        assert!(!res.fingerprint_changed_from("fp_44709d6fcb"));
+        assert!(res.fingerprint_changed_from("fp_eeff13170a"));
     }
 
     #[tokio::test]
@@ -562,6 +2548,16 @@ mod tests {
         let ret = get_weather_forecast(serde_json::from_str(&tool_call.function.arguments)?);
         assert_eq!(ret.unit, TemperatureUnit::Celsius);
         assert_eq!(ret.temperature, 22.2);
+
+        let mut req = get_tool_completion_request();
+        req.messages
+            .push(AssistantMessage::with_tool_calls(choice.message.tool_calls.clone()).into());
+        req.messages.push(ChatCompletionMessage::new_tool(
+            serde_json::to_string(&ret)?,
+            &tool_call.id,
+        ));
+        let res = SDK.chat_completion(req).await?;
+        assert_eq!(res.choices[0].finish_reason, FinishReason::Stop);
         Ok(())
     }
 
@@ -0,0 +1,329 @@
+use crate::{
+    Annotation, AssistantMessage, ChatCompleteUsage, ChatStreamResponse, FinishReason,
+    FunctionCall, ToolCall, ToolCallDelta,
+};
+use std::collections::BTreeMap;
+
+/// Rebuild the full assistant message from the chunks of a streaming chat completion. When n > 1, the chunks of
+/// each choice are accumulated separately, keyed by `ChatStreamChoice::index`.
+#[derive(Debug, Clone, Default)]
+pub struct ChatStreamAccumulator {
+    choices: BTreeMap<usize, ChoiceAccumulator>,
+    usage: Option<ChatCompleteUsage>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ChoiceAccumulator {
+    content: Option<String>,
+    reasoning_content: Option<String>,
+    role: Option<String>,
+    tool_calls: ToolCallAccumulator,
+    annotations: Vec<Annotation>,
+    finish_reason: Option<FinishReason>,
+}
+
+/// Reassemble complete tool calls from the fragments streamed in `Delta::tool_calls`.
+#[derive(Debug, Clone, Default)]
+pub struct ToolCallAccumulator {
+    tool_calls: BTreeMap<usize, ToolCall>,
+}
+
+impl ToolCallAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, deltas: &[ToolCallDelta]) {
+        for delta in deltas {
+            let tool_call = self
+                .tool_calls
+                .entry(delta.index)
+                .or_insert_with(|| ToolCall {
+                    id: String::new(),
+                    r#type: Default::default(),
+                    function: FunctionCall {
+                        name: String::new(),
+                        arguments: String::new(),
+                    },
+                });
+            if let Some(id) = &delta.id {
+                tool_call.id.push_str(id);
+            }
+            if let Some(r#type) = delta.r#type {
+                tool_call.r#type = r#type;
+            }
+            if let Some(function) = &delta.function {
+                if let Some(name) = &function.name {
+                    tool_call.function.name.push_str(name);
+                }
+                if let Some(arguments) = &function.arguments {
+                    tool_call.function.arguments.push_str(arguments);
+                }
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tool_calls.is_empty()
+    }
+
+    /// The complete tool calls, ordered by their index.
+    pub fn finish(self) -> Vec<ToolCall> {
+        self.tool_calls.into_values().collect()
+    }
+}
+
+impl ChatStreamAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, res: &ChatStreamResponse) {
+        if let Some(usage) = &res.usage {
+            self.usage = Some(usage.clone());
+        }
+        for choice in &res.choices {
+            let acc = self.choices.entry(choice.index).or_default();
+            let delta = &choice.delta;
+            if let Some(content) = &delta.content {
+                acc.content
+                    .get_or_insert_with(String::new)
+                    .push_str(content);
+            }
+            if let Some(reasoning_content) = &delta.reasoning_content {
+                acc.reasoning_content
+                    .get_or_insert_with(String::new)
+                    .push_str(reasoning_content);
+            }
+            if delta.role.is_some() {
+                acc.role = delta.role.clone();
+            }
+            if let Some(tool_calls) = &delta.tool_calls {
+                acc.tool_calls.push(tool_calls);
+            }
+            if let Some(annotations) = &delta.annotations {
+                acc.annotations.extend_from_slice(annotations);
+            }
+            if choice.finish_reason.is_some() {
+                acc.finish_reason = choice.finish_reason;
+            }
+        }
+    }
+
+    /// The content of the first choice received so far.
+    pub fn content(&self) -> Option<&str> {
+        self.first().and_then(|c| c.content.as_deref())
+    }
+
+    /// The reasoning content of the first choice received so far.
+    pub fn reasoning_content(&self) -> Option<&str> {
+        self.first().and_then(|c| c.reasoning_content.as_deref())
+    }
+
+    /// The reason the model stopped generating tokens for the first choice, once its last chunk is received.
+    pub fn finish_reason(&self) -> Option<FinishReason> {
+        self.first().and_then(|c| c.finish_reason)
+    }
+
+    /// The usage statistics, only available when `stream_options.include_usage` is set.
+    pub fn usage(&self) -> Option<&ChatCompleteUsage> {
+        self.usage.as_ref()
+    }
+
+    /// The assistant message of the first choice.
+    pub fn finish(self) -> AssistantMessage {
+        self.choices
+            .into_values()
+            .next()
+            .unwrap_or_default()
+            .finish()
+    }
+
+    /// The assistant messages of all choices, keyed by the choice index.
+    pub fn finish_all(self) -> BTreeMap<usize, AssistantMessage> {
+        self.choices
+            .into_iter()
+            .map(|(index, acc)| (index, acc.finish()))
+            .collect()
+    }
+
+    /// Whether no choice was received yet.
+    pub fn is_empty(&self) -> bool {
+        self.choices.is_empty()
+    }
+
+    fn first(&self) -> Option<&ChoiceAccumulator> {
+        self.choices.values().next()
+    }
+}
+
+impl ChoiceAccumulator {
+    fn finish(self) -> AssistantMessage {
+        AssistantMessage {
+            content: self.content,
+            reasoning_content: self.reasoning_content,
+            name: None,
+            tool_calls: self.tool_calls.finish(),
+            role: self.role,
+            annotations: self.annotations,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn chunk(delta: Value, finish_reason: Value) -> ChatStreamResponse {
+        chunk_with_index(0, delta, finish_reason)
+    }
+
+    fn chunk_with_index(index: usize, delta: Value, finish_reason: Value) -> ChatStreamResponse {
+        serde_json::from_value(json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion.chunk",
+            "created": 1700000000,
+            "model": "gpt-3.5-turbo-1106",
+            "system_fingerprint": null,
+            "choices": [{
+                "index": index,
+                "delta": delta,
+                "finish_reason": finish_reason,
+                "logprobs": null
+            }]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn accumulator_should_collect_annotations() {
+        let mut acc = ChatStreamAccumulator::new();
+        acc.push(&chunk(
+            json!({ "role": "assistant", "content": "See docs.rs." }),
+            Value::Null,
+        ));
+        acc.push(&chunk(
+            json!({
+                "annotations": [{
+                    "type": "url_citation",
+                    "url_citation": { "start_index": 4, "end_index": 11, "title": "Docs.rs", "url": "https://docs.rs" }
+                }]
+            }),
+            json!("stop"),
+        ));
+        let message = acc.finish();
+        assert_eq!(
+            message.url_citations().next().unwrap().url,
+            "https://docs.rs"
+        );
+    }
+
+    #[test]
+    fn chat_stream_finish_reason_should_be_typed() {
+        let res = chunk(json!({}), json!("length"));
+        assert_eq!(res.choices[0].finish_reason, Some(FinishReason::Length));
+        let res = chunk(json!({}), json!("provider_specific"));
+        assert_eq!(res.choices[0].finish_reason, Some(FinishReason::Unknown));
+        let res = chunk(json!({ "content": "Hi" }), Value::Null);
+        assert_eq!(res.choices[0].finish_reason, None);
+    }
+
+    #[test]
+    fn chat_stream_logprobs_should_deserialize() {
+        let res: ChatStreamResponse = serde_json::from_value(json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion.chunk",
+            "created": 1700000000,
+            "model": "gpt-4o-mini",
+            "system_fingerprint": null,
+            "choices": [{
+                "index": 0,
+                "delta": { "content": "Yes" },
+                "finish_reason": null,
+                "logprobs": {
+                    "content": [{
+                        "token": "Yes",
+                        "logprob": -0.01,
+                        "bytes": [89, 101, 115],
+                        "top_logprobs": [{ "token": "No", "logprob": -4.6, "bytes": [78, 111] }]
+                    }]
+                }
+            }]
+        }))
+        .unwrap();
+        let content = res.choices[0]
+            .logprobs
+            .as_ref()
+            .unwrap()
+            .content
+            .as_ref()
+            .unwrap();
+        assert_eq!(content[0].token, "Yes");
+        assert_eq!(content[0].top_logprobs[0].token, "No");
+    }
+
+    #[test]
+    fn chat_stream_accumulator_should_work() {
+        let chunks = [
+            chunk(json!({"role": "assistant", "content": ""}), Value::Null),
+            chunk(json!({"reasoning_content": "Think"}), Value::Null),
+            chunk(json!({"reasoning_content": " hard."}), Value::Null),
+            chunk(json!({"content": "Hello"}), Value::Null),
+            chunk(json!({"content": " world"}), Value::Null),
+            chunk(json!({}), json!("stop")),
+        ];
+        let mut acc = ChatStreamAccumulator::new();
+        chunks.iter().for_each(|c| acc.push(c));
+        assert_eq!(acc.reasoning_content(), Some("Think hard."));
+        assert_eq!(acc.finish_reason(), Some(FinishReason::Stop));
+        let message = acc.finish();
+        assert_eq!(message.content.as_deref(), Some("Hello world"));
+        assert_eq!(message.reasoning_content.as_deref(), Some("Think hard."));
+        assert_eq!(message.role.as_deref(), Some("assistant"));
+        assert!(message.tool_calls.is_empty());
+    }
+
+    #[test]
+    fn chat_stream_accumulator_should_keep_choices_separate() {
+        let chunks = [
+            chunk_with_index(0, json!({"role": "assistant", "content": "A"}), Value::Null),
+            chunk_with_index(1, json!({"role": "assistant", "content": "B"}), Value::Null),
+            chunk_with_index(1, json!({"content": "b"}), Value::Null),
+            chunk_with_index(0, json!({"content": "a"}), json!("stop")),
+            chunk_with_index(1, json!({}), json!("length")),
+        ];
+        let mut acc = ChatStreamAccumulator::new();
+        chunks.iter().for_each(|c| acc.push(c));
+        assert_eq!(acc.content(), Some("Aa"));
+        let messages = acc.finish_all();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[&0].content.as_deref(), Some("Aa"));
+        assert_eq!(messages[&1].content.as_deref(), Some("Bb"));
+    }
+
+    #[test]
+    fn tool_call_accumulator_should_work() {
+        let chunks = [
+            json!([{"index": 0, "id": "call_1", "type": "function", "function": {"name": "get_weather", "arguments": ""}}]),
+            json!([{"index": 0, "function": {"arguments": "{\"city\""}}]),
+            json!([{"index": 1, "id": "call_2", "type": "function", "function": {"name": "explain_mood", "arguments": "{}"}}]),
+            json!([{"index": 0, "function": {"arguments": ": \"Boston\"}"}}]),
+        ];
+        let mut acc = ToolCallAccumulator::new();
+        for tool_calls in chunks {
+            let res = chunk(
+                json!({ "content": null, "tool_calls": tool_calls }),
+                Value::Null,
+            );
+            acc.push(res.choices[0].delta.tool_calls.as_deref().unwrap());
+        }
+        let tool_calls = acc.finish();
+        assert_eq!(tool_calls.len(), 2);
+        assert_eq!(tool_calls[0].id, "call_1");
+        assert_eq!(tool_calls[0].function.name, "get_weather");
+        assert_eq!(tool_calls[0].function.arguments, "{\"city\": \"Boston\"}");
+        assert_eq!(tool_calls[1].id, "call_2");
+        assert_eq!(tool_calls[1].function.name, "explain_mood");
+    }
+}
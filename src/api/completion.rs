@@ -0,0 +1,321 @@
+use crate::{ChatCompleteModel, ChatCompleteUsage, FinishReason, IntoRequest, Stop};
+use derive_builder::Builder;
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A request to the legacy completions endpoint. With `suffix`, this is a fill-in-the-middle (FIM) completion,
+/// e.g. with DeepSeek's beta endpoint: `LlmSdk::new_with_base_url(token, "https://api.deepseek.com/beta")`.
+#[derive(Debug, Clone, Serialize, Builder)]
+#[builder(pattern = "mutable", build_fn(validate = "Self::validate"))]
+pub struct CompletionRequest {
+    /// ID of the model to use. Defaults to `ChatCompleteModel::Gpt3TurboInstruct`, as the chat models don't support
+    /// the completions endpoint.
+    #[builder(default = "ChatCompleteModel::Gpt3TurboInstruct")]
+    model: ChatCompleteModel,
+    /// The prompt to generate completions for.
+    #[builder(setter(into))]
+    prompt: String,
+    /// The suffix that comes after a completion of inserted text.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suffix: Option<String>,
+    /// The maximum number of tokens that can be generated in the completion.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<usize>,
+    /// Include the log probabilities on the logprobs most likely output tokens, as well the chosen tokens. The maximum value for logprobs is 5.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logprobs: Option<u8>,
+    /// Echo back the prompt in addition to the completion.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    echo: Option<bool>,
+    /// Number between -2.0 and 2.0. Positive values penalize new tokens based on their existing frequency in the text so far, decreasing the model's likelihood to repeat the same line verbatim.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f32>,
+    /// Number between -2.0 and 2.0. Positive values penalize new tokens based on whether they appear in the text so far, increasing the model's likelihood to talk about new topics.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f32>,
+    /// Up to 4 sequences where the API will stop generating further tokens. The returned text will not contain the stop sequence.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop: Option<Stop>,
+    /// What sampling temperature to use, between 0 and 2. Higher values like 0.8 will make the output more random, while lower values like 0.2 will make it more focused and deterministic.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    /// An alternative to sampling with temperature, called nucleus sampling, where the model considers the results of the tokens with top_p probability mass.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    /// A unique identifier representing your end-user, which can help OpenAI to monitor and detect abuse.
+    #[builder(default, setter(strip_option, into))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    /// Whether to stream back partial progress. Set by `LlmSdk::completion_stream`.
+    #[builder(setter(skip))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) stream: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompletionResponse {
+    /// A unique identifier for the completion.
+    pub id: String,
+    /// The list of completion choices the model generated for the input prompt.
+    pub choices: Vec<CompletionChoice>,
+    /// The Unix timestamp (in seconds) of when the completion was created.
+    pub created: usize,
+    /// The model used for completion.
+    pub model: ChatCompleteModel,
+    /// This fingerprint represents the backend configuration that the model runs with.
+    #[serde(default)]
+    pub system_fingerprint: Option<String>,
+    /// The object type, which is always text_completion.
+    pub object: String,
+    /// Usage statistics for the completion request.
+    pub usage: ChatCompleteUsage,
+    /// Fields the SDK doesn't model, e.g. provider-specific data.
+    #[cfg(feature = "extra-fields")]
+    #[serde(flatten)]
+    pub extra: crate::ExtraFields,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompletionChoice {
+    /// The generated text. For a FIM completion, this is the text between the prompt and the suffix.
+    pub text: String,
+    /// The index of the choice in the list of choices.
+    pub index: usize,
+    /// The reason the model stopped generating tokens.
+    pub finish_reason: FinishReason,
+    /// The log probabilities of the tokens, if logprobs is requested.
+    #[serde(default)]
+    pub logprobs: Option<CompletionLogProbs>,
+}
+
+/// A chunk of a streaming legacy completion. See `LlmSdk::completion_stream`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompletionStreamResponse {
+    pub id: String,
+    pub choices: Vec<CompletionStreamChoice>,
+    pub created: usize,
+    pub model: String,
+    pub object: String,
+    #[serde(default)]
+    pub system_fingerprint: Option<String>,
+    /// Usage statistics, only present in the last chunk of some providers.
+    #[serde(default)]
+    pub usage: Option<ChatCompleteUsage>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompletionStreamChoice {
+    /// The text generated since the previous chunk.
+    pub text: String,
+    pub index: usize,
+    /// The reason the model stopped generating tokens, only set in the last chunk of the choice.
+    #[serde(default)]
+    pub finish_reason: Option<FinishReason>,
+    #[serde(default)]
+    pub logprobs: Option<CompletionLogProbs>,
+}
+
+/// The log probabilities of a legacy completion, as parallel lists indexed by the token position.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CompletionLogProbs {
+    /// The tokens.
+    #[serde(default)]
+    pub tokens: Vec<String>,
+    /// The log probability of each token. The first token of an echoed prompt has none.
+    #[serde(default)]
+    pub token_logprobs: Vec<Option<f32>>,
+    /// The most likely tokens and their log probability at each position.
+    #[serde(default)]
+    pub top_logprobs: Option<Vec<Option<HashMap<String, f32>>>>,
+    /// The character offset of each token in the text.
+    #[serde(default)]
+    pub text_offset: Vec<usize>,
+}
+
+impl CompletionRequestBuilder {
+    fn validate(&self) -> Result<(), String> {
+        if let Some(Some(logprobs)) = self.logprobs {
+            if logprobs > 5 {
+                return Err(format!(
+                    "logprobs must be between 0 and 5, got {}",
+                    logprobs
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CompletionRequest {
+    pub fn new(model: ChatCompleteModel, prompt: impl Into<String>) -> Self {
+        CompletionRequestBuilder::default()
+            .model(model)
+            .prompt(prompt)
+            .build()
+            .unwrap()
+    }
+
+    pub fn model(&self) -> &ChatCompleteModel {
+        &self.model
+    }
+
+    /// A fill-in-the-middle completion: the model generates the text between `prompt` and `suffix`.
+    pub fn new_fim(
+        model: ChatCompleteModel,
+        prompt: impl Into<String>,
+        suffix: impl Into<String>,
+    ) -> Self {
+        CompletionRequestBuilder::default()
+            .model(model)
+            .prompt(prompt)
+            .suffix(suffix)
+            .build()
+            .unwrap()
+    }
+}
+
+impl CompletionResponse {
+    /// The time the completion was created.
+    #[cfg(feature = "chrono")]
+    pub fn created_at(&self) -> chrono::DateTime<chrono::Utc> {
+        crate::unix_timestamp(self.created as u64)
+    }
+}
+
+impl IntoRequest for CompletionRequest {
+    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        let url = format!("{}/completions", base_url);
+        client.post(url).json(&self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LlmSdk;
+    use anyhow::Result;
+    use serde_json::json;
+
+    #[test]
+    fn fim_request_should_serialize() -> Result<()> {
+        let req = CompletionRequest::new_fim(
+            ChatCompleteModel::DeepSeekCoder,
+            "def fib(a):",
+            "    return fib(a-1) + fib(a-2)",
+        );
+        assert_eq!(
+            serde_json::to_value(req)?,
+            json!({
+              "model": "deepseek-coder",
+              "prompt": "def fib(a):",
+              "suffix": "    return fib(a-1) + fib(a-2)"
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn completion_response_should_deserialize() -> Result<()> {
+        let res: CompletionResponse = serde_json::from_value(json!({
+          "id": "cmpl-123",
+          "object": "text_completion",
+          "created": 1718345013,
+          "model": "deepseek-coder",
+          "choices": [{ "text": "\n    if a <= 1:\n        return a\n", "index": 0, "logprobs": null, "finish_reason": "stop" }],
+          "usage": { "prompt_tokens": 12, "completion_tokens": 14, "total_tokens": 26 }
+        }))?;
+        assert_eq!(res.model, ChatCompleteModel::DeepSeekCoder);
+        assert_eq!(res.choices[0].finish_reason, FinishReason::Stop);
+        assert!(res.choices[0].text.contains("return a"));
+        Ok(())
+    }
+
+    #[test]
+    fn completion_logprobs_should_work() -> Result<()> {
+        let req = CompletionRequestBuilder::default()
+            .model(ChatCompleteModel::Gpt3TurboInstruct)
+            .prompt("Say this is a test")
+            .max_tokens(7usize)
+            .logprobs(2)
+            .echo(true)
+            .build()?;
+        assert_eq!(
+            serde_json::to_value(req)?,
+            json!({
+              "model": "gpt-3.5-turbo-instruct",
+              "prompt": "Say this is a test",
+              "max_tokens": 7,
+              "logprobs": 2,
+              "echo": true
+            })
+        );
+        // the chat models don't support the completions endpoint
+        let req = CompletionRequestBuilder::default()
+            .prompt("Say this is a test")
+            .build()?;
+        assert_eq!(req.model(), &ChatCompleteModel::Gpt3TurboInstruct);
+        let err = CompletionRequestBuilder::default()
+            .prompt("Say this is a test")
+            .logprobs(6)
+            .build()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            CompletionRequestBuilderError::ValidationError(_)
+        ));
+
+        let res: CompletionResponse = serde_json::from_value(json!({
+          "id": "cmpl-123",
+          "object": "text_completion",
+          "created": 1718345013,
+          "model": "gpt-3.5-turbo-instruct",
+          "choices": [{
+            "text": "Say this is a test.",
+            "index": 0,
+            "logprobs": {
+              "tokens": ["Say", "."],
+              "token_logprobs": [null, -0.2],
+              "top_logprobs": [null, { ".": -0.2, "!": -2.1 }],
+              "text_offset": [0, 18]
+            },
+            "finish_reason": "length"
+          }],
+          "usage": { "prompt_tokens": 5, "completion_tokens": 1, "total_tokens": 6 }
+        }))?;
+        let logprobs = res.choices[0].logprobs.as_ref().unwrap();
+        assert_eq!(logprobs.token_logprobs, [None, Some(-0.2)]);
+        assert_eq!(
+            logprobs.top_logprobs.as_ref().unwrap()[1].as_ref().unwrap()["!"],
+            -2.1
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn deepseek_fim_completion_should_work() -> Result<()> {
+        let sdk = LlmSdk::new_with_base_url(
+            std::env::var("DEEPSEEK_API_KEY")?,
+            "https://api.deepseek.com/beta",
+        );
+        let req = CompletionRequest::new_fim(
+            ChatCompleteModel::Other("deepseek-chat".into()),
+            "def fib(a):",
+            "    return fib(a-1) + fib(a-2)",
+        );
+        let res = sdk.completion(req).await?;
+        assert_eq!(res.object, "text_completion");
+        assert_eq!(res.choices.len(), 1);
+        Ok(())
+    }
+}
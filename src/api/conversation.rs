@@ -0,0 +1,138 @@
+use crate::{ChatCompletionMessage, ToolCall};
+
+/// A fluent builder of the messages of a chat completion, e.g.
+/// `Conversation::new().system("You are a poet.").user("Write a haiku.")`. It converts into the
+/// `Vec<ChatCompletionMessage>` the request expects, and can be collected from or iterated as messages.
+#[derive(Debug, Clone, Default)]
+pub struct Conversation {
+    messages: Vec<ChatCompletionMessage>,
+}
+
+impl Conversation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn system(mut self, content: impl Into<String>) -> Self {
+        self.messages
+            .push(ChatCompletionMessage::new_system(content, ""));
+        self
+    }
+
+    pub fn user(mut self, content: impl Into<String>) -> Self {
+        self.messages
+            .push(ChatCompletionMessage::new_user(content, ""));
+        self
+    }
+
+    pub fn assistant(mut self, content: impl Into<String>) -> Self {
+        self.messages
+            .push(ChatCompletionMessage::new_assistant(content, vec![]));
+        self
+    }
+
+    /// An assistant reply with tool calls, to be followed by the results of the calls, see `tool`.
+    pub fn assistant_with_tool_calls(
+        mut self,
+        content: impl Into<String>,
+        tool_calls: Vec<ToolCall>,
+    ) -> Self {
+        self.messages
+            .push(ChatCompletionMessage::new_assistant(content, tool_calls));
+        self
+    }
+
+    pub fn tool(mut self, content: impl Into<String>, tool_call_id: impl Into<String>) -> Self {
+        self.messages
+            .push(ChatCompletionMessage::new_tool(content, tool_call_id));
+        self
+    }
+
+    /// Append any message, e.g. a user message with images.
+    pub fn message(mut self, message: impl Into<ChatCompletionMessage>) -> Self {
+        self.messages.push(message.into());
+        self
+    }
+
+    pub fn messages(&self) -> &[ChatCompletionMessage] {
+        &self.messages
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+impl From<Conversation> for Vec<ChatCompletionMessage> {
+    fn from(conversation: Conversation) -> Self {
+        conversation.messages
+    }
+}
+
+impl FromIterator<ChatCompletionMessage> for Conversation {
+    fn from_iter<I: IntoIterator<Item = ChatCompletionMessage>>(iter: I) -> Self {
+        Self {
+            messages: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl Extend<ChatCompletionMessage> for Conversation {
+    fn extend<I: IntoIterator<Item = ChatCompletionMessage>>(&mut self, iter: I) {
+        self.messages.extend(iter);
+    }
+}
+
+impl IntoIterator for Conversation {
+    type Item = ChatCompletionMessage;
+    type IntoIter = std::vec::IntoIter<ChatCompletionMessage>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.messages.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Conversation {
+    type Item = &'a ChatCompletionMessage;
+    type IntoIter = std::slice::Iter<'a, ChatCompletionMessage>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.messages.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChatCompleteModel, ChatCompletionRequest};
+
+    #[test]
+    fn conversation_should_build_messages() {
+        let conversation = Conversation::new()
+            .system("You are a poet.")
+            .user("Write a haiku.")
+            .assistant("Autumn moonlight")
+            .user("Another one.");
+        assert_eq!(conversation.len(), 4);
+
+        let req = ChatCompletionRequest::new(ChatCompleteModel::Gpt4oMini, conversation.clone());
+        let json = serde_json::to_value(req).unwrap();
+        let roles: Vec<_> = json["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["role"].as_str().unwrap())
+            .collect();
+        assert_eq!(roles, ["system", "user", "assistant", "user"]);
+
+        let users: Conversation = conversation
+            .into_iter()
+            .filter(|m| matches!(m, ChatCompletionMessage::User(_)))
+            .collect();
+        assert_eq!(users.len(), 2);
+    }
+}
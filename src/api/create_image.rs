@@ -1,15 +1,17 @@
-use crate::IntoRequest;
+use crate::{IntoRequest, LlmError};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::Bytes;
 use derive_builder::Builder;
 use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Builder)]
-#[builder(pattern = "mutable")]
+#[builder(pattern = "mutable", build_fn(validate = "Self::validate"))]
 pub struct CreateImageRequest {
     /// A text description of the desired image(s). The maximum length is 4000 characters for dall-e-3.
     #[builder(setter(into))]
     prompt: String,
-    /// The model to use for image generation. Only support Dall-e-3
+    /// The model to use for image generation. Defaults to dall-e-3.
     #[builder(default)]
     model: ImageModel,
     /// The number of images to generate. Must be between 1 and 10. For dall-e-3, only n=1 is supported.
@@ -24,7 +26,8 @@ pub struct CreateImageRequest {
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
     response_format: Option<ImageResponseFormat>,
-    /// The size of the generated images. Must be one of 1024x1024, 1792x1024, or 1024x1792 for dall-e-3 models.
+    /// The size of the generated images. Must be one of `ImageModel::sizes` of the model, e.g. 1024x1024, 1792x1024,
+    /// or 1024x1792 for dall-e-3.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
     size: Option<ImageSize>,
@@ -36,13 +39,25 @@ pub struct CreateImageRequest {
     #[builder(default, setter(strip_option, into))]
     #[serde(skip_serializing_if = "Option::is_none")]
     user: Option<String>,
+    /// Stream the generation as events. Only supported for gpt-image-1. Set by `LlmSdk::create_image_stream`.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) stream: Option<bool>,
+    /// The number of partial images (0 to 3) to stream before the final image.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    partial_images: Option<u8>,
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
 pub enum ImageModel {
+    #[serde(rename = "dall-e-2")]
+    DallE2,
     #[serde(rename = "dall-e-3")]
     #[default]
     DallE3,
+    #[serde(rename = "gpt-image-1")]
+    GptImage1,
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
@@ -63,6 +78,10 @@ pub enum ImageResponseFormat {
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
 pub enum ImageSize {
+    #[serde(rename = "256x256")]
+    Small,
+    #[serde(rename = "512x512")]
+    Medium,
     #[serde(rename = "1024x1024")]
     #[default]
     Large,
@@ -70,6 +89,28 @@ pub enum ImageSize {
     LargeWide,
     #[serde(rename = "1024x1792")]
     LargeTall,
+    #[serde(rename = "1536x1024")]
+    Landscape,
+    #[serde(rename = "1024x1536")]
+    Portrait,
+    #[serde(rename = "auto")]
+    Auto,
+}
+
+impl ImageModel {
+    /// The image sizes supported by the model.
+    pub fn sizes(&self) -> &'static [ImageSize] {
+        match self {
+            Self::DallE2 => &[ImageSize::Small, ImageSize::Medium, ImageSize::Large],
+            Self::DallE3 => &[ImageSize::Large, ImageSize::LargeWide, ImageSize::LargeTall],
+            Self::GptImage1 => &[
+                ImageSize::Large,
+                ImageSize::Landscape,
+                ImageSize::Portrait,
+                ImageSize::Auto,
+            ],
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
@@ -84,6 +125,10 @@ pub enum ImageStyle {
 pub struct CreateImageResponse {
     pub created: u64,
     pub data: Vec<ImageObject>,
+    /// Fields the SDK doesn't model, e.g. provider-specific data.
+    #[cfg(feature = "extra-fields")]
+    #[serde(flatten)]
+    pub extra: crate::ExtraFields,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -92,10 +137,101 @@ pub struct ImageObject {
     pub b64_json: Option<String>,
     /// The URL of the generated image, if response_format is url (default).
     pub url: Option<String>,
-    /// The prompt that was used to generate the image, if there was any revision to the prompt.
+    /// The prompt that was used to generate the image, if there was any revision to the prompt. Empty for models
+    /// that don't revise the prompt.
+    #[serde(default)]
     pub revised_prompt: String,
 }
 
+impl ImageObject {
+    /// Decode the base64-encoded image. Fails if the image was returned as url, i.e. response_format is not b64_json.
+    pub fn decode(&self) -> Result<Bytes, LlmError> {
+        let b64_json = self.b64_json.as_deref().ok_or_else(|| {
+            LlmError::Decode("image has no b64_json, set response_format to b64_json".into())
+        })?;
+        decode_image(b64_json)
+    }
+}
+
+fn decode_image(b64_json: &str) -> Result<Bytes, LlmError> {
+    STANDARD
+        .decode(b64_json)
+        .map(Bytes::from)
+        .map_err(|e| LlmError::Decode(e.to_string()))
+}
+
+/// An event of a streaming image generation. See `LlmSdk::create_image_stream`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type")]
+pub enum CreateImageStreamEvent {
+    /// A partial image, i.e. a progressive preview of the final image.
+    #[serde(rename = "image_generation.partial_image")]
+    PartialImage {
+        /// The base64-encoded partial image.
+        b64_json: String,
+        /// The 0-based index of the partial image.
+        partial_image_index: usize,
+        /// The Unix timestamp (in seconds) of when the event was created.
+        created_at: u64,
+        size: Option<String>,
+        quality: Option<String>,
+        background: Option<String>,
+        output_format: Option<String>,
+    },
+    /// The final image.
+    #[serde(rename = "image_generation.completed")]
+    Completed {
+        /// The base64-encoded final image.
+        b64_json: String,
+        /// The Unix timestamp (in seconds) of when the event was created.
+        created_at: u64,
+        size: Option<String>,
+        quality: Option<String>,
+        background: Option<String>,
+        output_format: Option<String>,
+        /// The token usage of the generation.
+        usage: Option<ImageUsage>,
+    },
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ImageUsage {
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+    pub total_tokens: usize,
+}
+
+impl CreateImageStreamEvent {
+    /// The base64-encoded image of the event.
+    pub fn b64_json(&self) -> &str {
+        match self {
+            Self::PartialImage { b64_json, .. } | Self::Completed { b64_json, .. } => b64_json,
+        }
+    }
+
+    /// Decode the base64-encoded image of the event.
+    pub fn decode(&self) -> Result<Bytes, LlmError> {
+        decode_image(self.b64_json())
+    }
+
+    pub fn is_completed(&self) -> bool {
+        matches!(self, Self::Completed { .. })
+    }
+}
+
+impl CreateImageResponse {
+    /// Decode the base64-encoded images, ready to be written to disk. See `ImageObject::decode`.
+    pub fn decode(&self) -> Result<Vec<Bytes>, LlmError> {
+        self.data.iter().map(ImageObject::decode).collect()
+    }
+
+    /// The time the images were created.
+    #[cfg(feature = "chrono")]
+    pub fn created_at(&self) -> chrono::DateTime<chrono::Utc> {
+        crate::unix_timestamp(self.created)
+    }
+}
+
 impl IntoRequest for CreateImageRequest {
     fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
         let url = format!("{}/images/generations", base_url);
@@ -103,6 +239,35 @@ impl IntoRequest for CreateImageRequest {
     }
 }
 
+impl CreateImageRequestBuilder {
+    fn validate(&self) -> Result<(), String> {
+        let model = self.model.unwrap_or_default();
+        if let Some(Some(size)) = self.size {
+            if !model.sizes().contains(&size) {
+                return Err(format!("size {:?} is not supported by {:?}", size, model));
+            }
+        }
+        if let Some(Some(n)) = self.n {
+            let max = if model == ImageModel::DallE3 { 1 } else { 10 };
+            if !(1..=max).contains(&n) {
+                return Err(format!(
+                    "n must be between 1 and {} for {:?}, got {}",
+                    max, model, n
+                ));
+            }
+        }
+        if let Some(Some(partial_images)) = self.partial_images {
+            if partial_images > 3 {
+                return Err(format!(
+                    "partial_images must be between 0 and 3, got {}",
+                    partial_images
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
 impl CreateImageRequest {
     pub fn new(prompt: impl Into<String>) -> Self {
         CreateImageRequestBuilder::default()
@@ -110,6 +275,10 @@ impl CreateImageRequest {
             .build()
             .unwrap()
     }
+
+    pub fn model(&self) -> &ImageModel {
+        &self.model
+    }
 }
 
 #[cfg(test)]
@@ -151,6 +320,93 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn create_image_request_with_size_should_validate() -> Result<()> {
+        let req = CreateImageRequestBuilder::default()
+            .prompt("draw a cute caterpillar")
+            .model(ImageModel::DallE2)
+            .size(ImageSize::Small)
+            .n(4)
+            .response_format(ImageResponseFormat::B64Json)
+            .build()?;
+        assert_eq!(
+            serde_json::to_value(req)?,
+            json!({
+              "prompt": "draw a cute caterpillar",
+              "model": "dall-e-2",
+              "size": "256x256",
+              "n": 4,
+              "response_format": "b64_json",
+            })
+        );
+
+        let err = CreateImageRequestBuilder::default()
+            .prompt("draw a cute caterpillar")
+            .size(ImageSize::Small)
+            .build()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            CreateImageRequestBuilderError::ValidationError(_)
+        ));
+
+        let err = CreateImageRequestBuilder::default()
+            .prompt("draw a cute caterpillar")
+            .n(2)
+            .build()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            CreateImageRequestBuilderError::ValidationError(_)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn create_image_stream_event_should_deserialize() -> Result<()> {
+        let event: CreateImageStreamEvent = serde_json::from_value(json!({
+          "type": "image_generation.partial_image",
+          "b64_json": "aGVsbG8=",
+          "created_at": 1620000000,
+          "size": "1024x1024",
+          "quality": "high",
+          "background": "transparent",
+          "output_format": "png",
+          "partial_image_index": 0
+        }))?;
+        assert!(!event.is_completed());
+        assert_eq!(event.b64_json(), "aGVsbG8=");
+
+        let event: CreateImageStreamEvent = serde_json::from_value(json!({
+          "type": "image_generation.completed",
+          "b64_json": "d29ybGQ=",
+          "created_at": 1620000000,
+          "usage": { "input_tokens": 10, "output_tokens": 20, "total_tokens": 30 }
+        }))?;
+        assert!(event.is_completed());
+        assert_eq!(event.b64_json(), "d29ybGQ=");
+        Ok(())
+    }
+
+    #[test]
+    fn create_image_response_should_decode() -> Result<()> {
+        let res: CreateImageResponse = serde_json::from_value(json!({
+          "created": 1589478378,
+          "data": [{ "b64_json": "aGVsbG8=" }, { "b64_json": "d29ybGQ=" }]
+        }))?;
+        assert_eq!(
+            res.decode()?,
+            vec![Bytes::from("hello"), Bytes::from("world")]
+        );
+
+        let res: CreateImageResponse = serde_json::from_value(json!({
+          "created": 1589478378,
+          "data": [{ "url": "https://example.com/image.png", "revised_prompt": "a caterpillar" }]
+        }))?;
+        assert!(matches!(res.decode(), Err(LlmError::Decode(_))));
+        Ok(())
+    }
+
     // this test is too expensive to run, skip for CI
     #[tokio::test]
     #[ignore]
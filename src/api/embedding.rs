@@ -1,7 +1,8 @@
 use crate::IntoRequest;
+use base64::{engine::general_purpose::STANDARD, Engine};
 use derive_builder::Builder;
 use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize};
 
 #[derive(Debug, Clone, Serialize, Builder)]
 #[builder(pattern = "mutable")]
@@ -11,7 +12,11 @@ pub struct EmbeddingRequest {
     /// ID of the model to use. You can use the List models API to see all of your available models, or see our Model overview for descriptions of them.
     #[builder(default)]
     model: EmbeddingModel,
-    /// The format to return the embeddings in. Can be either float or base64.
+    /// The number of dimensions the resulting output embeddings should have. Only supported in text-embedding-3 and later models.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dimensions: Option<usize>,
+    /// The format to return the embeddings in. Can be either float or base64. base64 is much smaller on the wire, and is decoded into floats transparently.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
     encoding_format: Option<EmbeddingEncodingFormat>,
@@ -21,12 +26,17 @@ pub struct EmbeddingRequest {
     user: Option<String>,
 }
 
-// currently we don't support array of integers, or array of array of integers
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 #[serde(untagged)]
 pub enum EmbeddingInput {
+    /// A single text.
     String(String),
+    /// A batch of texts.
     StringArray(Vec<String>),
+    /// A single array of token ids.
+    Tokens(Vec<u32>),
+    /// A batch of token id arrays.
+    TokensArray(Vec<Vec<u32>>),
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
@@ -34,6 +44,10 @@ pub enum EmbeddingModel {
     #[default]
     #[serde(rename = "text-embedding-ada-002")]
     TextEmbeddingAda002,
+    #[serde(rename = "text-embedding-3-small")]
+    TextEmbedding3Small,
+    #[serde(rename = "text-embedding-3-large")]
+    TextEmbedding3Large,
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
@@ -50,6 +64,10 @@ pub struct EmbeddingResponse {
     pub data: Vec<EmbeddingData>,
     pub model: String,
     pub usage: EmbeddingUsage,
+    /// Fields the SDK doesn't model, e.g. provider-specific data.
+    #[cfg(feature = "extra-fields")]
+    #[serde(flatten)]
+    pub extra: crate::ExtraFields,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -63,11 +81,39 @@ pub struct EmbeddingData {
     /// The index of the embedding in the list of embeddings.
     pub index: usize,
     /// The embedding vector, which is a list of floats. The length of vector depends on the model as listed in the embedding guide.
+    #[serde(deserialize_with = "deserialize_embedding")]
     pub embedding: Vec<f32>,
     /// The object type, which is always "embedding".
     pub object: String,
 }
 
+/// The embedding is either a list of floats, or a base64 string of little-endian f32s if encoding_format is base64.
+fn deserialize_embedding<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<f32>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Embedding {
+        Float(Vec<f32>),
+        Base64(String),
+    }
+
+    match Embedding::deserialize(deserializer)? {
+        Embedding::Float(v) => Ok(v),
+        Embedding::Base64(s) => {
+            let bytes = STANDARD.decode(s).map_err(de::Error::custom)?;
+            if bytes.len() % 4 != 0 {
+                return Err(de::Error::custom(format!(
+                    "base64 embedding length {} is not a multiple of 4",
+                    bytes.len()
+                )));
+            }
+            Ok(bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect())
+        }
+    }
+}
+
 impl IntoRequest for EmbeddingRequest {
     fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
         let url = format!("{}/embeddings", base_url);
@@ -89,6 +135,10 @@ impl EmbeddingRequest {
             .build()
             .unwrap()
     }
+
+    pub fn model(&self) -> &EmbeddingModel {
+        &self.model
+    }
 }
 
 impl From<String> for EmbeddingInput {
@@ -109,6 +159,18 @@ impl From<&[String]> for EmbeddingInput {
     }
 }
 
+impl From<Vec<u32>> for EmbeddingInput {
+    fn from(tokens: Vec<u32>) -> Self {
+        Self::Tokens(tokens)
+    }
+}
+
+impl From<Vec<Vec<u32>>> for EmbeddingInput {
+    fn from(tokens: Vec<Vec<u32>>) -> Self {
+        Self::TokensArray(tokens)
+    }
+}
+
 impl From<&str> for EmbeddingInput {
     fn from(s: &str) -> Self {
         Self::String(s.to_owned())
@@ -120,6 +182,67 @@ mod tests {
     use super::*;
     use crate::SDK;
     use anyhow::Result;
+    use serde_json::json;
+
+    #[test]
+    fn embedding_request_should_serialize() -> Result<()> {
+        let req = EmbeddingRequestBuilder::default()
+            .input("hello".into())
+            .model(EmbeddingModel::TextEmbedding3Small)
+            .dimensions(256)
+            .encoding_format(EmbeddingEncodingFormat::Float)
+            .build()?;
+        assert_eq!(
+            serde_json::to_value(req)?,
+            json!({
+              "input": "hello",
+              "model": "text-embedding-3-small",
+              "dimensions": 256,
+              "encoding_format": "float",
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn embedding_input_should_serialize() -> Result<()> {
+        let inputs: [(EmbeddingInput, serde_json::Value); 4] = [
+            ("a".into(), json!("a")),
+            (
+                vec!["a".to_string(), "b".to_string()].into(),
+                json!(["a", "b"]),
+            ),
+            (vec![1u32, 2].into(), json!([1, 2])),
+            (vec![vec![1u32, 2], vec![3]].into(), json!([[1, 2], [3]])),
+        ];
+        for (input, expected) in inputs {
+            assert_eq!(serde_json::to_value(input)?, expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn embedding_response_should_decode_base64() -> Result<()> {
+        let floats = [0.5f32, -1.25, 3.0];
+        let encoded = STANDARD.encode(
+            floats
+                .iter()
+                .flat_map(|f| f.to_le_bytes())
+                .collect::<Vec<_>>(),
+        );
+        let res: EmbeddingResponse = serde_json::from_value(json!({
+          "object": "list",
+          "data": [
+            { "object": "embedding", "index": 0, "embedding": encoded },
+            { "object": "embedding", "index": 1, "embedding": [0.5, -1.25, 3.0] }
+          ],
+          "model": "text-embedding-3-small",
+          "usage": { "prompt_tokens": 2, "total_tokens": 2 }
+        }))?;
+        assert_eq!(res.data[0].embedding, floats);
+        assert_eq!(res.data[1].embedding, floats);
+        Ok(())
+    }
 
     #[tokio::test]
     #[ignore]
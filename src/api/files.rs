@@ -0,0 +1,188 @@
+use crate::IntoRequest;
+use derive_builder::Builder;
+use reqwest::multipart::{Form, Part};
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+
+#[derive(Debug, Clone, Builder)]
+#[builder(pattern = "mutable")]
+pub struct UploadFileRequest {
+    /// The File object (not file name) to be uploaded.
+    file: Vec<u8>,
+    /// The name of the file, e.g. batch.jsonl.
+    #[builder(setter(into))]
+    filename: String,
+    /// The intended purpose of the uploaded file. Use batch for the Batch API and fine-tune for fine-tuning.
+    purpose: FilePurpose,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumString, Display)]
+pub enum FilePurpose {
+    #[serde(rename = "assistants")]
+    #[strum(serialize = "assistants")]
+    Assistants,
+    #[serde(rename = "assistants_output")]
+    #[strum(serialize = "assistants_output")]
+    AssistantsOutput,
+    #[serde(rename = "batch")]
+    #[strum(serialize = "batch")]
+    Batch,
+    #[serde(rename = "batch_output")]
+    #[strum(serialize = "batch_output")]
+    BatchOutput,
+    #[serde(rename = "fine-tune")]
+    #[strum(serialize = "fine-tune")]
+    FineTune,
+    #[serde(rename = "fine-tune-results")]
+    #[strum(serialize = "fine-tune-results")]
+    FineTuneResults,
+    #[serde(rename = "vision")]
+    #[strum(serialize = "vision")]
+    Vision,
+    /// A purpose the SDK doesn't know yet, e.g. of files uploaded elsewhere. Not for uploads.
+    #[serde(other)]
+    #[strum(serialize = "unknown")]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FileObject {
+    /// The file identifier, which can be referenced in the API endpoints.
+    pub id: String,
+    /// The object type, which is always file.
+    pub object: String,
+    /// The size of the file, in bytes.
+    pub bytes: u64,
+    /// The Unix timestamp (in seconds) for when the file was created.
+    pub created_at: u64,
+    /// The name of the file.
+    pub filename: String,
+    /// The intended purpose of the file.
+    pub purpose: FilePurpose,
+    /// Deprecated. The current status of the file, which can be either uploaded, processed, or error.
+    #[serde(default)]
+    pub status: Option<String>,
+    /// Deprecated. For details on why a fine-tuning training file failed validation, see the error field on the fine-tuning job.
+    #[serde(default)]
+    pub status_details: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FileList {
+    /// The object type, which is always list.
+    pub object: String,
+    pub data: Vec<FileObject>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeleteFileResponse {
+    pub id: String,
+    pub object: String,
+    pub deleted: bool,
+}
+
+/// Requests on existing files, which only carry the file id.
+#[derive(Debug, Clone)]
+pub(crate) enum FileRequest {
+    List,
+    Retrieve(String),
+    Delete(String),
+    Content(String),
+}
+
+impl UploadFileRequest {
+    pub fn new(file: Vec<u8>, filename: impl Into<String>, purpose: FilePurpose) -> Self {
+        UploadFileRequestBuilder::default()
+            .file(file)
+            .filename(filename)
+            .purpose(purpose)
+            .build()
+            .unwrap()
+    }
+
+    fn into_form(self) -> Form {
+        let part = Part::bytes(self.file)
+            .file_name(self.filename)
+            .mime_str("application/octet-stream")
+            .unwrap();
+        Form::new()
+            .part("file", part)
+            .text("purpose", self.purpose.to_string())
+    }
+}
+
+impl IntoRequest for UploadFileRequest {
+    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        let url = format!("{}/files", base_url);
+        client.post(url).multipart(self.into_form())
+    }
+}
+
+impl IntoRequest for FileRequest {
+    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        match self {
+            FileRequest::List => client.get(format!("{}/files", base_url)),
+            FileRequest::Retrieve(id) => client.get(format!("{}/files/{}", base_url, id)),
+            FileRequest::Delete(id) => client.delete(format!("{}/files/{}", base_url, id)),
+            FileRequest::Content(id) => client.get(format!("{}/files/{}/content", base_url, id)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SDK;
+    use anyhow::Result;
+    use serde_json::json;
+
+    #[test]
+    fn file_object_should_deserialize() -> Result<()> {
+        let file: FileObject = serde_json::from_value(json!({
+          "id": "file-abc123",
+          "object": "file",
+          "bytes": 120000,
+          "created_at": 1677610602,
+          "filename": "mydata.jsonl",
+          "purpose": "fine-tune",
+        }))?;
+        assert_eq!(file.id, "file-abc123");
+        assert_eq!(file.purpose, FilePurpose::FineTune);
+        assert_eq!(file.status, None);
+
+        let file: FileObject = serde_json::from_value(json!({
+          "id": "file-abc124",
+          "object": "file",
+          "bytes": 1024,
+          "created_at": 1677610602,
+          "filename": "notes.pdf",
+          "purpose": "user_data",
+        }))?;
+        assert_eq!(file.purpose, FilePurpose::Unknown);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn file_lifecycle_should_work() -> Result<()> {
+        let data = br#"{"custom_id": "request-1", "method": "POST", "url": "/v1/embeddings", "body": {"model": "text-embedding-ada-002", "input": "hello"}}"#;
+        let req = UploadFileRequest::new(data.to_vec(), "batch.jsonl", FilePurpose::Batch);
+        let file = SDK.upload_file(req).await?;
+        assert_eq!(file.filename, "batch.jsonl");
+        assert_eq!(file.purpose, FilePurpose::Batch);
+
+        let retrieved = SDK.retrieve_file(&file.id).await?;
+        assert_eq!(retrieved.id, file.id);
+
+        let files = SDK.list_files().await?;
+        assert!(files.data.iter().any(|f| f.id == file.id));
+
+        let content = SDK.retrieve_file_content(&file.id).await?;
+        assert_eq!(&content[..], &data[..]);
+
+        let deleted = SDK.delete_file(&file.id).await?;
+        assert!(deleted.deleted);
+        Ok(())
+    }
+}
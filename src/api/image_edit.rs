@@ -0,0 +1,225 @@
+use crate::{ImageModel, ImageResponseFormat, ImageSize, IntoRequest};
+use derive_builder::Builder;
+use reqwest::multipart::{Form, Part};
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Builder)]
+#[builder(pattern = "mutable", build_fn(validate = "Self::validate"))]
+pub struct CreateImageEditRequest {
+    /// The image to edit. Must be a valid PNG file, less than 4MB, and square. If mask is not provided, image must have transparency, which will be used as the mask.
+    image: Vec<u8>,
+    /// An additional image whose fully transparent areas (e.g. where alpha is zero) indicate where image should be edited. Must be a valid PNG file, less than 4MB, and have the same dimensions as image.
+    #[builder(default, setter(strip_option))]
+    mask: Option<Vec<u8>>,
+    /// A text description of the desired image(s). The maximum length is 1000 characters.
+    #[builder(setter(into))]
+    prompt: String,
+    /// The model to use for image edits. Only dall-e-2 and gpt-image-1 are supported. Defaults to dall-e-2.
+    #[builder(default = "ImageModel::DallE2")]
+    model: ImageModel,
+    /// The number of images to generate. Must be between 1 and 10.
+    #[builder(default, setter(strip_option))]
+    n: Option<usize>,
+    /// The size of the generated images. Must be one of `ImageModel::sizes` of the model.
+    #[builder(default, setter(strip_option))]
+    size: Option<ImageSize>,
+    /// The format in which the generated images are returned. Must be one of url or b64_json.
+    #[builder(default, setter(strip_option))]
+    response_format: Option<ImageResponseFormat>,
+    /// A unique identifier representing your end-user, which can help OpenAI to monitor and detect abuse.
+    #[builder(default, setter(strip_option, into))]
+    user: Option<String>,
+}
+
+#[derive(Debug, Clone, Builder)]
+#[builder(pattern = "mutable", build_fn(validate = "Self::validate"))]
+pub struct CreateImageVariationRequest {
+    /// The image to use as the basis for the variation(s). Must be a valid PNG file, less than 4MB, and square.
+    image: Vec<u8>,
+    /// The model to use for image variations. Only dall-e-2 is supported.
+    #[builder(default = "ImageModel::DallE2")]
+    model: ImageModel,
+    /// The number of images to generate. Must be between 1 and 10.
+    #[builder(default, setter(strip_option))]
+    n: Option<usize>,
+    /// The size of the generated images. Must be one of 256x256, 512x512, or 1024x1024.
+    #[builder(default, setter(strip_option))]
+    size: Option<ImageSize>,
+    /// The format in which the generated images are returned. Must be one of url or b64_json.
+    #[builder(default, setter(strip_option))]
+    response_format: Option<ImageResponseFormat>,
+    /// A unique identifier representing your end-user, which can help OpenAI to monitor and detect abuse.
+    #[builder(default, setter(strip_option, into))]
+    user: Option<String>,
+}
+
+impl CreateImageEditRequest {
+    pub fn new(image: Vec<u8>, prompt: impl Into<String>) -> Self {
+        CreateImageEditRequestBuilder::default()
+            .image(image)
+            .prompt(prompt)
+            .build()
+            .unwrap()
+    }
+
+    pub fn model(&self) -> &ImageModel {
+        &self.model
+    }
+
+    fn into_form(self) -> Form {
+        let mut form = Form::new()
+            .part("image", png_part(self.image, "image.png"))
+            .text("prompt", self.prompt)
+            .text("model", form_value(self.model));
+        if let Some(mask) = self.mask {
+            form = form.part("mask", png_part(mask, "mask.png"));
+        }
+        add_options(form, self.n, self.size, self.response_format, self.user)
+    }
+}
+
+impl CreateImageVariationRequest {
+    pub fn new(image: Vec<u8>) -> Self {
+        CreateImageVariationRequestBuilder::default()
+            .image(image)
+            .build()
+            .unwrap()
+    }
+
+    pub fn model(&self) -> &ImageModel {
+        &self.model
+    }
+
+    fn into_form(self) -> Form {
+        let form = Form::new()
+            .part("image", png_part(self.image, "image.png"))
+            .text("model", form_value(self.model));
+        add_options(form, self.n, self.size, self.response_format, self.user)
+    }
+}
+
+impl CreateImageEditRequestBuilder {
+    fn validate(&self) -> Result<(), String> {
+        let model = self.model.unwrap_or(ImageModel::DallE2);
+        if model == ImageModel::DallE3 {
+            return Err("image edits are not supported by dall-e-3".into());
+        }
+        validate_options(model, self.n, self.size)
+    }
+}
+
+impl CreateImageVariationRequestBuilder {
+    fn validate(&self) -> Result<(), String> {
+        let model = self.model.unwrap_or(ImageModel::DallE2);
+        if model != ImageModel::DallE2 {
+            return Err(format!("image variations are not supported by {:?}", model));
+        }
+        validate_options(model, self.n, self.size)
+    }
+}
+
+fn validate_options(
+    model: ImageModel,
+    n: Option<Option<usize>>,
+    size: Option<Option<ImageSize>>,
+) -> Result<(), String> {
+    if let Some(Some(size)) = size {
+        if !model.sizes().contains(&size) {
+            return Err(format!("size {:?} is not supported by {:?}", size, model));
+        }
+    }
+    if let Some(Some(n)) = n {
+        if !(1..=10).contains(&n) {
+            return Err(format!("n must be between 1 and 10, got {}", n));
+        }
+    }
+    Ok(())
+}
+
+fn png_part(data: Vec<u8>, file_name: &'static str) -> Part {
+    Part::bytes(data)
+        .file_name(file_name)
+        .mime_str("image/png")
+        .unwrap()
+}
+
+/// The form value of a unit enum, i.e. its serde name.
+fn form_value(value: impl Serialize) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(s)) => s,
+        _ => unreachable!("form values are unit enums"),
+    }
+}
+
+fn add_options(
+    mut form: Form,
+    n: Option<usize>,
+    size: Option<ImageSize>,
+    response_format: Option<ImageResponseFormat>,
+    user: Option<String>,
+) -> Form {
+    if let Some(n) = n {
+        form = form.text("n", n.to_string());
+    }
+    if let Some(size) = size {
+        form = form.text("size", form_value(size));
+    }
+    if let Some(response_format) = response_format {
+        form = form.text("response_format", form_value(response_format));
+    }
+    if let Some(user) = user {
+        form = form.text("user", user);
+    }
+    form
+}
+
+impl IntoRequest for CreateImageEditRequest {
+    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        let url = format!("{}/images/edits", base_url);
+        client.post(url).multipart(self.into_form())
+    }
+}
+
+impl IntoRequest for CreateImageVariationRequest {
+    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        let url = format!("{}/images/variations", base_url);
+        client.post(url).multipart(self.into_form())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_edit_request_should_validate_model_and_size() {
+        let req = CreateImageEditRequestBuilder::default()
+            .image(vec![])
+            .prompt("add a hat")
+            .size(ImageSize::Medium)
+            .build();
+        assert!(req.is_ok());
+
+        let err = CreateImageEditRequestBuilder::default()
+            .image(vec![])
+            .prompt("add a hat")
+            .model(ImageModel::DallE3)
+            .build()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            CreateImageEditRequestBuilderError::ValidationError(_)
+        ));
+
+        let err = CreateImageVariationRequestBuilder::default()
+            .image(vec![])
+            .size(ImageSize::LargeWide)
+            .build()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            CreateImageVariationRequestBuilderError::ValidationError(_)
+        ));
+    }
+}
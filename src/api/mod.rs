@@ -1,11 +1,25 @@
+mod batch;
 mod chat_completion;
+mod chat_stream;
+mod completion;
+mod conversation;
 mod create_image;
 mod embedding;
+mod files;
+mod image_edit;
 mod speech;
+mod tool_registry;
 mod whisper;
 
+pub use batch::*;
 pub use chat_completion::*;
+pub use chat_stream::*;
+pub use completion::*;
+pub use conversation::*;
 pub use create_image::*;
 pub use embedding::*;
+pub use files::*;
+pub use image_edit::*;
 pub use speech::*;
+pub use tool_registry::*;
 pub use whisper::*;
@@ -4,7 +4,7 @@ use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
 use serde::Serialize;
 
 #[derive(Debug, Clone, Serialize, Builder)]
-#[builder(pattern = "mutable")]
+#[builder(pattern = "mutable", build_fn(validate = "Self::validate"))]
 pub struct SpeechRequest {
     /// One of the available TTS models: tts-1 or tts-1-hd
     #[builder(default)]
@@ -15,7 +15,7 @@ pub struct SpeechRequest {
     /// The voice to use when generating the audio. Supported voices are alloy, echo, fable, onyx, nova, and shimmer. Previews of the voices are available in the Text to speech guide.
     #[builder(default)]
     voice: SpeechVoice,
-    /// The format to audio in. Supported formats are mp3, opus, aac, and flac.
+    /// The format to audio in. Supported formats are mp3, opus, aac, flac, wav, and pcm.
     #[builder(default)]
     response_format: SpeechResponseFormat,
     /// The speed of the generated audio. Select a value from 0.25 to 4.0. 1.0 is the default.
@@ -53,6 +53,10 @@ pub enum SpeechResponseFormat {
     Opus,
     Aac,
     Flac,
+    /// Uncompressed WAV audio, suitable for low-latency applications to avoid decoding overhead.
+    Wav,
+    /// Raw samples in 24kHz (16-bit signed, little-endian), without the header.
+    Pcm,
 }
 
 impl IntoRequest for SpeechRequest {
@@ -69,6 +73,21 @@ impl SpeechRequest {
             .build()
             .unwrap()
     }
+
+    pub fn model(&self) -> &SpeechModel {
+        &self.model
+    }
+}
+
+impl SpeechRequestBuilder {
+    fn validate(&self) -> Result<(), String> {
+        if let Some(Some(speed)) = self.speed {
+            if !(0.25..=4.0).contains(&speed) {
+                return Err(format!("speed must be between 0.25 and 4.0, got {}", speed));
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -76,6 +95,39 @@ mod tests {
     use super::*;
     use crate::SDK;
     use anyhow::Result;
+    use serde_json::json;
+
+    #[test]
+    fn speech_request_should_serialize() -> Result<()> {
+        let req = SpeechRequestBuilder::default()
+            .input("hello")
+            .response_format(SpeechResponseFormat::Pcm)
+            .speed(1.5)
+            .build()?;
+        assert_eq!(
+            serde_json::to_value(req)?,
+            json!({
+              "model": "tts-1",
+              "input": "hello",
+              "voice": "nova",
+              "response_format": "pcm",
+              "speed": 1.5
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn speech_request_should_reject_out_of_range_speed() {
+        for speed in [0.2, 4.5] {
+            let err = SpeechRequestBuilder::default()
+                .input("hello")
+                .speed(speed)
+                .build()
+                .unwrap_err();
+            assert!(matches!(err, SpeechRequestBuilderError::ValidationError(_)));
+        }
+    }
 
     #[tokio::test]
     #[ignore]
@@ -0,0 +1,200 @@
+use crate::{LlmError, ToSchema, Tool, ToolCall, ToolMessage};
+use jsonschema::JSONSchema;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt};
+
+type Handler = Box<dyn FnMut(&str) -> Result<Value, LlmError> + Send>;
+
+/// Map tool names to typed handlers, so that the tool calls generated by the model can be dispatched without
+/// matching on names and deserializing arguments by hand.
+#[derive(Default)]
+pub struct ToolRegistry {
+    tools: BTreeMap<String, RegisteredTool>,
+}
+
+struct RegisteredTool {
+    tool: Tool,
+    handler: Handler,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a function tool. Its parameters schema is generated from `Args`. The arguments of the tool calls
+    /// are validated against the schema, then deserialized into `Args` before `handler` is invoked. Registering
+    /// the same name again replaces the previous tool.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name is not a valid function name. See `Tool::new_function`.
+    pub fn register<Args, F, E>(
+        &mut self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+        mut handler: F,
+    ) -> &mut Self
+    where
+        Args: DeserializeOwned + JsonSchema,
+        F: FnMut(Args) -> Result<Value, E> + Send + 'static,
+        E: fmt::Display,
+    {
+        let name = name.into();
+        let tool = Tool::new_function::<Args>(name.clone(), description);
+        let schema = JSONSchema::compile(&Args::to_schema())
+            .unwrap_or_else(|e| panic!("invalid parameters schema for tool {}: {}", name, e));
+        let tool_name = name.clone();
+        let handler: Handler = Box::new(move |arguments| {
+            let value: Value =
+                serde_json::from_str(arguments).map_err(|e| LlmError::ToolArguments {
+                    name: tool_name.clone(),
+                    errors: vec![format!("invalid JSON: {}", e)],
+                })?;
+            if let Err(errors) = schema.validate(&value) {
+                return Err(LlmError::ToolArguments {
+                    name: tool_name.clone(),
+                    errors: errors
+                        .map(|e| match e.instance_path.to_string() {
+                            path if path.is_empty() => e.to_string(),
+                            path => format!("{}: {}", path, e),
+                        })
+                        .collect(),
+                });
+            }
+            let args = serde_json::from_value(value).map_err(|e| LlmError::ToolArguments {
+                name: tool_name.clone(),
+                errors: vec![e.to_string()],
+            })?;
+            handler(args).map_err(|e| LlmError::Tool {
+                name: tool_name.clone(),
+                message: e.to_string(),
+            })
+        });
+        self.tools.insert(name, RegisteredTool { tool, handler });
+        self
+    }
+
+    /// The registered tools, to be sent with the request.
+    pub fn tools(&self) -> Vec<Tool> {
+        self.tools.values().map(|t| t.tool.clone()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    /// Invoke the handler of the tool call, and build the tool message with its result. A string result is sent
+    /// as is, other values are sent as JSON.
+    pub fn dispatch(&mut self, tool_call: &ToolCall) -> Result<ToolMessage, LlmError> {
+        let name = &tool_call.function.name;
+        let registered = self.tools.get_mut(name).ok_or_else(|| LlmError::Tool {
+            name: name.clone(),
+            message: "unknown tool".into(),
+        })?;
+        let content = match (registered.handler)(&tool_call.function.arguments)? {
+            Value::String(s) => s,
+            v => v.to_string(),
+        };
+        Ok(ToolMessage::new(content, &tool_call.id))
+    }
+}
+
+impl fmt::Debug for ToolRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ToolRegistry")
+            .field("tools", &self.tools.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FunctionCall, ToolType};
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[allow(dead_code)]
+    #[derive(Debug, Deserialize, JsonSchema)]
+    struct GetWeatherArgs {
+        /// The city to get the weather for.
+        city: String,
+        /// The number of days to forecast.
+        days: Option<u8>,
+    }
+
+    fn tool_call(name: &str, arguments: &str) -> ToolCall {
+        ToolCall {
+            id: "call_abc123".into(),
+            r#type: ToolType::Function,
+            function: FunctionCall {
+                name: name.into(),
+                arguments: arguments.into(),
+            },
+        }
+    }
+
+    fn registry() -> ToolRegistry {
+        let mut registry = ToolRegistry::new();
+        registry.register(
+            "get_weather_forecast",
+            "Get the weather forecast for a city.",
+            |args: GetWeatherArgs| match args.city.as_str() {
+                "Boston" => Ok(json!({ "temperature": 22.2 })),
+                city => Err(format!("unknown city {}", city)),
+            },
+        );
+        registry
+    }
+
+    #[test]
+    fn dispatch_should_invoke_typed_handler() {
+        let mut registry = registry();
+        assert_eq!(registry.tools().len(), 1);
+        let message = registry
+            .dispatch(&tool_call("get_weather_forecast", r#"{"city":"Boston"}"#))
+            .unwrap();
+        assert_eq!(message.tool_call_id(), "call_abc123");
+        assert_eq!(message.content(), r#"{"temperature":22.2}"#);
+    }
+
+    #[test]
+    fn dispatch_should_fail_on_unknown_tool_or_bad_arguments() {
+        let mut registry = registry();
+        for call in [
+            tool_call("explain_mood", "{}"),
+            tool_call("get_weather_forecast", r#"{"city":"Paris"}"#),
+        ] {
+            let err = registry.dispatch(&call).unwrap_err();
+            assert!(matches!(err, LlmError::Tool { ref name, .. } if *name == call.function.name));
+        }
+    }
+
+    #[test]
+    fn dispatch_should_validate_arguments_against_schema() {
+        let mut registry = registry();
+        let err = registry
+            .dispatch(&tool_call(
+                "get_weather_forecast",
+                r#"{"town":"Boston","days":-1}"#,
+            ))
+            .unwrap_err();
+        match err {
+            LlmError::ToolArguments { name, errors } => {
+                assert_eq!(name, "get_weather_forecast");
+                assert_eq!(errors.len(), 2, "{:?}", errors);
+                assert!(errors.iter().any(|e| e.contains("city")));
+                assert!(errors.iter().any(|e| e.starts_with("/days")));
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        let err = registry
+            .dispatch(&tool_call("get_weather_forecast", "{city: Boston}"))
+            .unwrap_err();
+        assert!(err.to_string().contains("invalid JSON"));
+    }
+}
@@ -6,7 +6,7 @@ use serde::Deserialize;
 use strum::{Display, EnumString};
 
 #[derive(Debug, Clone, Builder)]
-#[builder(pattern = "mutable")]
+#[builder(pattern = "mutable", build_fn(validate = "Self::validate"))]
 pub struct WhisperRequest {
     /// The audio file object (not file name) to transcribe/translate, in one of these formats: flac, mp3, mp4, mpeg, mpga, m4a, ogg, wav, or webm.
     file: Vec<u8>,
@@ -25,8 +25,11 @@ pub struct WhisperRequest {
     /// The sampling temperature, between 0 and 1. Higher values like 0.8 will make the output more random, while lower values like 0.2 will make it more focused and deterministic. If set to 0, the model will use log probability to automatically increase the temperature until certain thresholds are hit.
     #[builder(default, setter(strip_option))]
     temperature: Option<f32>,
+    /// The timestamp granularities to populate for this transcription. response_format must be set verbose_json to use timestamp granularities. Either or both of these options are supported: word, or segment. Note: There is no additional latency for segment timestamps, but generating word timestamps incurs additional latency.
+    #[builder(default, setter(into))]
+    timestamp_granularities: Vec<TimestampGranularity>,
 
-    request_type: WhisperRequestType,
+    pub(crate) request_type: WhisperRequestType,
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, EnumString, Display)]
@@ -47,6 +50,13 @@ pub enum WhisperResponseFormat {
     Vtt,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum TimestampGranularity {
+    Word,
+    Segment,
+}
+
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, EnumString, Display)]
 pub enum WhisperRequestType {
     #[default]
@@ -54,12 +64,67 @@ pub enum WhisperRequestType {
     Translation,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct WhisperResponse {
+    /// The transcribed text.
     pub text: String,
+    /// The language of the input audio. Only present for verbose_json.
+    #[serde(default)]
+    pub language: Option<String>,
+    /// The duration of the input audio in seconds. Only present for verbose_json.
+    #[serde(default)]
+    pub duration: Option<f32>,
+    /// Extracted words and their corresponding timestamps. Only present for verbose_json with word granularity.
+    #[serde(default)]
+    pub words: Option<Vec<WhisperWord>>,
+    /// Segments of the transcribed text and their corresponding details. Only present for verbose_json.
+    #[serde(default)]
+    pub segments: Option<Vec<WhisperSegment>>,
+    /// Fields the SDK doesn't model, e.g. provider-specific data.
+    #[cfg(feature = "extra-fields")]
+    #[serde(flatten)]
+    pub extra: crate::ExtraFields,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct WhisperWord {
+    /// The text content of the word.
+    pub word: String,
+    /// Start time of the word in seconds.
+    pub start: f32,
+    /// End time of the word in seconds.
+    pub end: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct WhisperSegment {
+    /// Unique identifier of the segment.
+    pub id: usize,
+    /// Seek offset of the segment.
+    pub seek: usize,
+    /// Start time of the segment in seconds.
+    pub start: f32,
+    /// End time of the segment in seconds.
+    pub end: f32,
+    /// Text content of the segment.
+    pub text: String,
+    /// Array of token IDs for the text content.
+    pub tokens: Vec<u32>,
+    /// Temperature parameter used for generating the segment.
+    pub temperature: f32,
+    /// Average logprob of the segment. If the value is lower than -1, consider the logprobs failed.
+    pub avg_logprob: f32,
+    /// Compression ratio of the segment. If the value is greater than 2.4, consider the compression failed.
+    pub compression_ratio: f32,
+    /// Probability of no speech in the segment. If the value is higher than 1.0 and the avg_logprob is below -1, consider this segment silent.
+    pub no_speech_prob: f32,
 }
 
 impl WhisperRequest {
+    pub fn model(&self) -> &WhisperModel {
+        &self.model
+    }
+
     pub fn transcription(data: Vec<u8>) -> Self {
         WhisperRequestBuilder::default()
             .file(data)
@@ -96,11 +161,37 @@ impl WhisperRequest {
         } else {
             form
         };
-        if let Some(temperature) = self.temperature {
+        form = if let Some(temperature) = self.temperature {
             form.text("temperature", temperature.to_string())
         } else {
             form
+        };
+        self.timestamp_granularities
+            .iter()
+            .fold(form, |form, granularity| {
+                form.text("timestamp_granularities[]", granularity.to_string())
+            })
+    }
+}
+
+impl WhisperRequestBuilder {
+    fn validate(&self) -> Result<(), String> {
+        if let Some(Some(temperature)) = self.temperature {
+            if !(0.0..=1.0).contains(&temperature) {
+                return Err(format!(
+                    "temperature must be between 0 and 1, got {}",
+                    temperature
+                ));
+            }
+        }
+        if let Some(granularities) = &self.timestamp_granularities {
+            if !granularities.is_empty()
+                && self.response_format != Some(WhisperResponseFormat::VerboseJson)
+            {
+                return Err("timestamp_granularities requires response_format verbose_json".into());
+            }
         }
+        Ok(())
     }
 }
 
@@ -121,6 +212,82 @@ mod tests {
     use anyhow::Result;
     use std::fs;
 
+    #[test]
+    fn timestamp_granularities_should_require_verbose_json() {
+        let err = WhisperRequestBuilder::default()
+            .file(vec![])
+            .timestamp_granularities(vec![TimestampGranularity::Word])
+            .request_type(WhisperRequestType::Transcription)
+            .build()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            WhisperRequestBuilderError::ValidationError(_)
+        ));
+
+        let req = WhisperRequestBuilder::default()
+            .file(vec![])
+            .response_format(WhisperResponseFormat::VerboseJson)
+            .timestamp_granularities(vec![
+                TimestampGranularity::Word,
+                TimestampGranularity::Segment,
+            ])
+            .request_type(WhisperRequestType::Transcription)
+            .build();
+        assert!(req.is_ok());
+    }
+
+    #[test]
+    fn temperature_should_be_between_0_and_1() {
+        let build = |temperature: f32| {
+            WhisperRequestBuilder::default()
+                .file(vec![])
+                .temperature(temperature)
+                .request_type(WhisperRequestType::Transcription)
+                .build()
+        };
+        assert!(build(0.0).is_ok());
+        assert!(build(1.0).is_ok());
+        for temperature in [-0.1, 1.5] {
+            assert!(matches!(
+                build(temperature),
+                Err(WhisperRequestBuilderError::ValidationError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn verbose_json_response_should_deserialize() -> Result<()> {
+        let res: WhisperResponse = serde_json::from_value(serde_json::json!({
+          "task": "transcribe",
+          "language": "english",
+          "duration": 2.8,
+          "text": "The quick brown fox.",
+          "words": [
+            { "word": "The", "start": 0.0, "end": 0.24 },
+            { "word": "quick", "start": 0.24, "end": 0.5 }
+          ],
+          "segments": [{
+            "id": 0,
+            "seek": 0,
+            "start": 0.0,
+            "end": 2.8,
+            "text": " The quick brown fox.",
+            "tokens": [50364, 440, 1702],
+            "temperature": 0.0,
+            "avg_logprob": -0.28,
+            "compression_ratio": 0.86,
+            "no_speech_prob": 0.01
+          }]
+        }))?;
+        assert_eq!(res.language.as_deref(), Some("english"));
+        let words = res.words.unwrap();
+        assert_eq!(words[1].word, "quick");
+        assert_eq!(words[1].end, 0.5);
+        assert_eq!(res.segments.unwrap()[0].end, 2.8);
+        Ok(())
+    }
+
     #[tokio::test]
     #[ignore]
     async fn transcription_should_work() -> Result<()> {
@@ -159,6 +326,36 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    #[ignore]
+    async fn transcription_with_timestamp_granularities_should_work() -> Result<()> {
+        let data = fs::read("fixtures/speech.mp3")?;
+        let req = WhisperRequestBuilder::default()
+            .file(data)
+            .response_format(WhisperResponseFormat::VerboseJson)
+            .timestamp_granularities(vec![
+                TimestampGranularity::Word,
+                TimestampGranularity::Segment,
+            ])
+            .request_type(WhisperRequestType::Transcription)
+            .build()?;
+        let res = SDK.whisper(req).await?;
+        assert_eq!(res.text, "The quick brown fox jumped over the lazy dog.");
+        assert!(!res.words.unwrap().is_empty());
+        assert!(!res.segments.unwrap().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn translate_with_transcription_request_should_work() -> Result<()> {
+        let data = fs::read("fixtures/chinese.mp3")?;
+        let req = WhisperRequest::transcription(data);
+        let res = SDK.translate(req).await?;
+        assert!(res.text.starts_with("The red scarf"));
+        Ok(())
+    }
+
     #[tokio::test]
     #[ignore]
     async fn translate_should_work() -> Result<()> {
@@ -0,0 +1,252 @@
+//! A blocking client, for code that doesn't run in an async context, e.g. CLI tools and build scripts. It wraps
+//! the async `LlmSdk` and drives it on an internal single-threaded runtime, so the methods mirror the async ones
+//! minus `async`. Don't call them from within an async runtime, use the async `LlmSdk` there instead.
+
+use crate::{
+    AssistantMessage, BatchList, BatchObject, ChatCompletionMessage, ChatCompletionRequest,
+    ChatCompletionResponse, ChatStreamResponse, CompletionRequest, CompletionResponse,
+    CompletionStreamResponse, CreateBatchRequest, CreateImageEditRequest, CreateImageRequest,
+    CreateImageResponse, CreateImageStreamEvent, CreateImageVariationRequest, DeleteFileResponse,
+    Delta, EmbeddingRequest, EmbeddingResponse, FileList, FileObject, LlmError, ResponseMeta,
+    SpeechRequest, ToolRegistry, UploadFileRequest, WhisperRequest, WhisperResponse,
+};
+use bytes::Bytes;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{future::Future, sync::Arc};
+use tokio::runtime::{Builder, Runtime};
+
+/// The blocking counterpart of `crate::LlmSdk`. Like the async client, it is cheap to clone.
+#[derive(Debug, Clone)]
+pub struct LlmSdk {
+    inner: crate::LlmSdk,
+    runtime: Arc<Runtime>,
+}
+
+impl From<crate::LlmSdk> for LlmSdk {
+    /// Wrap an async client, e.g. one built with `LlmSdkBuilder`.
+    fn from(inner: crate::LlmSdk) -> Self {
+        let runtime = Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("failed to create the tokio runtime");
+        Self {
+            inner,
+            runtime: Arc::new(runtime),
+        }
+    }
+}
+
+impl LlmSdk {
+    pub fn new(token: impl Into<String>) -> Self {
+        crate::LlmSdk::new(token).into()
+    }
+
+    pub fn new_with_base_url(token: impl Into<String>, base_url: impl Into<String>) -> Self {
+        crate::LlmSdk::new_with_base_url(token, base_url).into()
+    }
+
+    /// See `crate::LlmSdk::from_env`.
+    pub fn from_env() -> Result<Self, LlmError> {
+        crate::LlmSdk::from_env().map(Into::into)
+    }
+
+    /// The wrapped async client.
+    pub fn inner(&self) -> &crate::LlmSdk {
+        &self.inner
+    }
+
+    fn block_on<T>(&self, fut: impl Future<Output = T>) -> T {
+        self.runtime.block_on(fut)
+    }
+
+    pub fn chat_completion(
+        &self,
+        req: ChatCompletionRequest,
+    ) -> Result<ChatCompletionResponse, LlmError> {
+        self.block_on(self.inner.chat_completion(req))
+    }
+
+    pub fn chat_completion_with_meta(
+        &self,
+        req: ChatCompletionRequest,
+    ) -> Result<(ChatCompletionResponse, ResponseMeta), LlmError> {
+        self.block_on(self.inner.chat_completion_with_meta(req))
+    }
+
+    pub fn chat(
+        &self,
+        messages: Vec<ChatCompletionMessage>,
+    ) -> Result<ChatCompletionResponse, LlmError> {
+        self.block_on(self.inner.chat(messages))
+    }
+
+    pub fn run_with_tools(
+        &self,
+        req: ChatCompletionRequest,
+        registry: &mut ToolRegistry,
+        max_turns: usize,
+    ) -> Result<AssistantMessage, LlmError> {
+        self.block_on(self.inner.run_with_tools(req, registry, max_turns))
+    }
+
+    pub fn chat_stream(
+        &self,
+        req: ChatCompletionRequest,
+        f: impl FnMut(&ChatStreamResponse),
+    ) -> Result<(), LlmError> {
+        self.block_on(self.inner.chat_stream(req, f))
+    }
+
+    pub fn chat_stream_message(
+        &self,
+        req: ChatCompletionRequest,
+        f: impl FnMut(&ChatStreamResponse),
+    ) -> Result<AssistantMessage, LlmError> {
+        self.block_on(self.inner.chat_stream_message(req, f))
+    }
+
+    pub fn chat_stream_by_index(
+        &self,
+        req: ChatCompletionRequest,
+        f: impl FnMut(usize, &Delta),
+    ) -> Result<(), LlmError> {
+        self.block_on(self.inner.chat_stream_by_index(req, f))
+    }
+
+    pub fn completion(&self, req: CompletionRequest) -> Result<CompletionResponse, LlmError> {
+        self.block_on(self.inner.completion(req))
+    }
+
+    pub fn completion_stream(
+        &self,
+        req: CompletionRequest,
+        f: impl FnMut(&CompletionStreamResponse),
+    ) -> Result<(), LlmError> {
+        self.block_on(self.inner.completion_stream(req, f))
+    }
+
+    pub fn create_image(&self, req: CreateImageRequest) -> Result<CreateImageResponse, LlmError> {
+        self.block_on(self.inner.create_image(req))
+    }
+
+    pub fn create_image_stream(
+        &self,
+        req: CreateImageRequest,
+        f: impl FnMut(&CreateImageStreamEvent),
+    ) -> Result<(), LlmError> {
+        self.block_on(self.inner.create_image_stream(req, f))
+    }
+
+    pub fn create_image_edit(
+        &self,
+        req: CreateImageEditRequest,
+    ) -> Result<CreateImageResponse, LlmError> {
+        self.block_on(self.inner.create_image_edit(req))
+    }
+
+    pub fn create_image_variation(
+        &self,
+        req: CreateImageVariationRequest,
+    ) -> Result<CreateImageResponse, LlmError> {
+        self.block_on(self.inner.create_image_variation(req))
+    }
+
+    pub fn speech(&self, req: SpeechRequest) -> Result<Bytes, LlmError> {
+        self.block_on(self.inner.speech(req))
+    }
+
+    pub fn whisper(&self, req: WhisperRequest) -> Result<WhisperResponse, LlmError> {
+        self.block_on(self.inner.whisper(req))
+    }
+
+    pub fn translate(&self, req: WhisperRequest) -> Result<WhisperResponse, LlmError> {
+        self.block_on(self.inner.translate(req))
+    }
+
+    pub fn embedding(&self, req: EmbeddingRequest) -> Result<EmbeddingResponse, LlmError> {
+        self.block_on(self.inner.embedding(req))
+    }
+
+    pub fn upload_file(&self, req: UploadFileRequest) -> Result<FileObject, LlmError> {
+        self.block_on(self.inner.upload_file(req))
+    }
+
+    pub fn list_files(&self) -> Result<FileList, LlmError> {
+        self.block_on(self.inner.list_files())
+    }
+
+    pub fn retrieve_file(&self, id: &str) -> Result<FileObject, LlmError> {
+        self.block_on(self.inner.retrieve_file(id))
+    }
+
+    pub fn delete_file(&self, id: &str) -> Result<DeleteFileResponse, LlmError> {
+        self.block_on(self.inner.delete_file(id))
+    }
+
+    pub fn retrieve_file_content(&self, id: &str) -> Result<Bytes, LlmError> {
+        self.block_on(self.inner.retrieve_file_content(id))
+    }
+
+    pub fn create_batch(&self, req: CreateBatchRequest) -> Result<BatchObject, LlmError> {
+        self.block_on(self.inner.create_batch(req))
+    }
+
+    pub fn retrieve_batch(&self, id: &str) -> Result<BatchObject, LlmError> {
+        self.block_on(self.inner.retrieve_batch(id))
+    }
+
+    pub fn cancel_batch(&self, id: &str) -> Result<BatchObject, LlmError> {
+        self.block_on(self.inner.cancel_batch(id))
+    }
+
+    pub fn list_batches(&self) -> Result<BatchList, LlmError> {
+        self.block_on(self.inner.list_batches())
+    }
+
+    pub fn post_json<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: impl Serialize,
+    ) -> Result<T, LlmError> {
+        self.block_on(self.inner.post_json(path, body))
+    }
+
+    pub fn get_json<T: DeserializeOwned>(&self, path: &str) -> Result<T, LlmError> {
+        self.block_on(self.inner.get_json(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    #[test]
+    fn blocking_sdk_should_work_outside_async_context() {
+        // the mock server needs a runtime of its own, the blocking client is called from the test thread
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let server = rt.block_on(async {
+            let server = MockServer::start().await;
+            Mock::given(method("POST"))
+                .and(path("/embeddings"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "object": "list",
+                    "data": [{ "object": "embedding", "index": 0, "embedding": [0.1, 0.2] }],
+                    "model": "text-embedding-ada-002-v2",
+                    "usage": { "prompt_tokens": 1, "total_tokens": 1 }
+                })))
+                .expect(1)
+                .mount(&server)
+                .await;
+            server
+        });
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let res = sdk.embedding(EmbeddingRequest::new("hello")).unwrap();
+        assert_eq!(res.data[0].embedding, vec![0.1, 0.2]);
+        rt.block_on(server.verify());
+    }
+}
@@ -0,0 +1,221 @@
+use crate::LlmError;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::{Request, Response};
+use reqwest_middleware::{Middleware, Next, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use task_local_extensions::Extensions;
+
+/// Records the HTTP interactions of the SDK to a JSON file and replays them, like VCR: record once against the real
+/// API, then run the tests of your code offline and deterministically. Set it with `LlmSdkBuilder::cassette`.
+///
+/// Requests are matched by method and path (including the query), in the recorded order. Request headers are not
+/// recorded, so the API key never ends up in the cassette. Responses are buffered while recording, so streaming
+/// responses arrive at once when replayed.
+#[derive(Clone)]
+pub struct Cassette {
+    inner: Arc<CassetteInner>,
+}
+
+struct CassetteInner {
+    /// The file to write to in record mode, None in replay mode.
+    path: Option<PathBuf>,
+    interactions: Mutex<Vec<Interaction>>,
+    /// Whether each interaction was already replayed.
+    used: Mutex<Vec<bool>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Interaction {
+    request: RecordedRequest,
+    response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedRequest {
+    method: String,
+    path: String,
+    #[serde(default)]
+    body: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedResponse {
+    status: u16,
+    headers: BTreeMap<String, String>,
+    /// The body if it is valid UTF-8, e.g. JSON or SSE.
+    #[serde(default)]
+    body: String,
+    /// The base64 encoded body otherwise, e.g. speech audio or file contents.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body_base64: Option<String>,
+}
+
+/// The error of a request that has no recorded interaction in a replayed cassette.
+#[derive(Debug)]
+struct CassetteMiss(String);
+
+impl fmt::Display for CassetteMiss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no recorded interaction for {}", self.0)
+    }
+}
+
+impl std::error::Error for CassetteMiss {}
+
+impl Cassette {
+    /// Record every interaction, (over)writing the cassette file after each response.
+    pub fn record(path: impl Into<PathBuf>) -> Self {
+        Self::new(Some(path.into()), vec![])
+    }
+
+    /// Replay the interactions of a recorded cassette file, without sending any request.
+    pub fn replay(path: impl AsRef<Path>) -> std::result::Result<Self, LlmError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(|e| {
+            LlmError::Config(format!("failed to read cassette {}: {}", path.display(), e))
+        })?;
+        let interactions =
+            serde_json::from_str(&content).map_err(|source| LlmError::Deserialize {
+                source,
+                body: content.clone(),
+            })?;
+        Ok(Self::new(None, interactions))
+    }
+
+    fn new(path: Option<PathBuf>, interactions: Vec<Interaction>) -> Self {
+        let used = vec![false; interactions.len()];
+        Self {
+            inner: Arc::new(CassetteInner {
+                path,
+                interactions: Mutex::new(interactions),
+                used: Mutex::new(used),
+            }),
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.inner.path.is_some()
+    }
+
+    /// The number of recorded interactions.
+    pub fn len(&self) -> usize {
+        self.inner.interactions.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn replay_response(&self, req: &RecordedRequest) -> Result<Response> {
+        let interactions = self.inner.interactions.lock().unwrap();
+        let mut used = self.inner.used.lock().unwrap();
+        let index = interactions
+            .iter()
+            .zip(used.iter())
+            .position(|(i, used)| {
+                !used && i.request.method == req.method && i.request.path == req.path
+            })
+            .ok_or_else(|| {
+                reqwest_middleware::Error::middleware(CassetteMiss(format!(
+                    "{} {}",
+                    req.method, req.path
+                )))
+            })?;
+        used[index] = true;
+        to_response(&interactions[index].response)
+    }
+
+    async fn record_response(&self, req: RecordedRequest, res: Response) -> Result<Response> {
+        let status = res.status().as_u16();
+        let headers = res
+            .headers()
+            .iter()
+            .filter_map(|(k, v)| Some((k.to_string(), v.to_str().ok()?.to_string())))
+            .collect();
+        let body = res.bytes().await?;
+        let (body, body_base64) = match std::str::from_utf8(&body) {
+            Ok(body) => (body.to_string(), None),
+            Err(_) => (String::new(), Some(STANDARD.encode(&body))),
+        };
+        let response = RecordedResponse {
+            status,
+            headers,
+            body,
+            body_base64,
+        };
+        let ret = to_response(&response);
+        let mut interactions = self.inner.interactions.lock().unwrap();
+        interactions.push(Interaction {
+            request: req,
+            response,
+        });
+        if let Some(path) = &self.inner.path {
+            let content = serde_json::to_string_pretty(&*interactions)
+                .map_err(reqwest_middleware::Error::middleware)?;
+            fs::write(path, content).map_err(reqwest_middleware::Error::middleware)?;
+        }
+        ret
+    }
+}
+
+fn to_response(recorded: &RecordedResponse) -> Result<Response> {
+    let mut builder = http::Response::builder().status(recorded.status);
+    for (k, v) in &recorded.headers {
+        builder = builder.header(k, v);
+    }
+    let body = match &recorded.body_base64 {
+        Some(encoded) => STANDARD
+            .decode(encoded)
+            .map_err(reqwest_middleware::Error::middleware)?,
+        None => recorded.body.clone().into_bytes(),
+    };
+    let res = builder
+        .body(body)
+        .map_err(reqwest_middleware::Error::middleware)?;
+    Ok(res.into())
+}
+
+impl fmt::Debug for Cassette {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cassette")
+            .field("path", &self.inner.path)
+            .field("interactions", &self.len())
+            .finish()
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for Cassette {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        let url = req.url();
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        let body = req.body().and_then(|b| b.as_bytes()).map(|b| {
+            serde_json::from_slice(b)
+                .unwrap_or_else(|_| String::from_utf8_lossy(b).into_owned().into())
+        });
+        let recorded = RecordedRequest {
+            method: req.method().to_string(),
+            path,
+            body,
+        };
+        if !self.is_recording() {
+            return self.replay_response(&recorded);
+        }
+        let res = next.run(req, extensions).await?;
+        self.record_response(recorded, res).await
+    }
+}
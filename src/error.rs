@@ -0,0 +1,136 @@
+use crate::AssistantMessage;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum LlmError {
+    /// The API returned a non-success status code.
+    #[error("API failed ({status}): {}", error.message)]
+    Api {
+        /// The HTTP status code of the response.
+        status: u16,
+        /// The parsed error body. If the body is not a valid error envelope, `message` holds the raw text.
+        error: Box<ApiErrorBody>,
+        /// The `x-request-id` header of the response, which OpenAI asks for in support requests.
+        request_id: Option<String>,
+        /// The delay suggested by the server before retrying, e.g. the `retry-after` header of a 429 response.
+        retry_after: Option<std::time::Duration>,
+    },
+    /// The request could not be sent or the response could not be read.
+    #[error("transport error: {0}")]
+    Transport(#[from] reqwest_middleware::Error),
+    /// The response body is not in the expected shape. `body` holds the raw text that came back, e.g. the fields of
+    /// a provider-specific response the SDK doesn't model.
+    #[error("failed to deserialize response: {source}")]
+    Deserialize {
+        source: serde_json::Error,
+        body: String,
+    },
+    /// The SDK could not be configured, e.g. a required environment variable is missing.
+    #[error("configuration error: {0}")]
+    Config(String),
+    /// The input was rejected before sending the request.
+    #[error("validation error: {0}")]
+    Validation(String),
+    /// A tool call could not be dispatched, e.g. the tool is unknown, its arguments are invalid or its handler failed.
+    #[error("tool {name} failed: {message}")]
+    Tool { name: String, message: String },
+    /// The arguments of a tool call don't match the parameters schema of the tool. The message lists every
+    /// violation, so it can be fed back to the model as the tool result.
+    #[error("invalid arguments for tool {name}: {}", errors.join("; "))]
+    ToolArguments { name: String, errors: Vec<String> },
+    /// The model still requested tool calls after the maximum number of turns. See `LlmSdk::run_with_tools`.
+    #[error("tool calls not finished after {0} turns")]
+    MaxTurnsExceeded(usize),
+    /// The payload of a response could not be decoded, e.g. an image without valid base64 data.
+    #[error("decode error: {0}")]
+    Decode(String),
+    /// The SDK was called in a way that can't work, e.g. with a request the called method doesn't support.
+    #[error("misuse: {0}")]
+    Misuse(String),
+    /// The request was rejected without being sent, as the circuit breaker is open after consecutive failures.
+    /// See `LlmSdkBuilder::circuit_breaker_threshold`.
+    #[error("circuit breaker open, retry in {retry_in:?}")]
+    CircuitOpen { retry_in: std::time::Duration },
+    /// The streaming response is malformed.
+    #[error("stream error: {0}")]
+    Stream(String),
+    /// The stream broke off after some chunks were received, e.g. the connection dropped. `partial` holds the
+    /// assistant message received so far. See `LlmSdk::chat_stream_message`.
+    #[error("stream interrupted: {source}")]
+    StreamInterrupted {
+        partial: Box<AssistantMessage>,
+        source: Box<LlmError>,
+    },
+    /// The request was cancelled by the caller. See `with_cancellation`.
+    #[error("request cancelled")]
+    Cancelled,
+}
+
+impl LlmError {
+    /// The `x-request-id` of the failed response, if the API returned one.
+    pub fn request_id(&self) -> Option<&str> {
+        match self {
+            Self::Api { request_id, .. } => request_id.as_deref(),
+            Self::StreamInterrupted { source, .. } => source.request_id(),
+            _ => None,
+        }
+    }
+
+    /// The delay suggested by the server before retrying a failed request, after the retries of the SDK.
+    pub fn retry_after(&self) -> Option<std::time::Duration> {
+        match self {
+            Self::Api { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// The partial assistant message of an interrupted stream.
+    pub fn partial_message(&self) -> Option<&AssistantMessage> {
+        match self {
+            Self::StreamInterrupted { partial, .. } => Some(partial),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for LlmError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Transport(e.into())
+    }
+}
+
+/// The error object returned by OpenAI compatible APIs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiErrorBody {
+    /// A human-readable error message.
+    pub message: String,
+    /// The error type, e.g. `invalid_request_error`.
+    #[serde(default)]
+    pub r#type: Option<String>,
+    /// The error code, e.g. `insufficient_quota`.
+    #[serde(default)]
+    pub code: Option<String>,
+    /// The request parameter that caused the error, if any.
+    #[serde(default)]
+    pub param: Option<String>,
+}
+
+/// The envelope wrapping the error object: `{"error": {...}}`.
+#[derive(Debug, Deserialize)]
+struct ApiErrorEnvelope {
+    error: ApiErrorBody,
+}
+
+impl ApiErrorBody {
+    /// Parse the error envelope from the response body, falling back to the raw text as message.
+    pub(crate) fn parse(text: String) -> Self {
+        match serde_json::from_str::<ApiErrorEnvelope>(&text) {
+            Ok(envelope) => envelope.error,
+            Err(_) => Self {
+                message: text,
+                ..Default::default()
+            },
+        }
+    }
+}
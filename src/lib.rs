@@ -1,39 +1,182 @@
 mod api;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+mod cassette;
+mod error;
+mod meta;
 mod middleware;
+mod pricing;
+mod sse;
+#[cfg(feature = "tiktoken")]
+mod tokens;
 
 pub use api::*;
+pub use cassette::Cassette;
+pub use error::{ApiErrorBody, LlmError};
+pub use meta::{with_meta, ResponseMeta};
+pub use pricing::{ModelPrice, PriceTable};
+pub use reqwest::{Certificate, Identity, Proxy};
+pub use retry_policies::Jitter;
+#[cfg(feature = "tiktoken")]
+pub use tokens::{count_tokens, trim_to_fit};
 
-use anyhow::{anyhow, Result};
 use bytes::Bytes;
 use derive_builder::Builder;
-use futures_util::{future, StreamExt};
-use middleware::RetryMiddleware;
-use reqwest::Response;
+use futures_util::{
+    future::{self, Either},
+    pin_mut, FutureExt, Stream, StreamExt,
+};
+use middleware::{CircuitBreakerMiddleware, CircuitOpen, RetryMiddleware};
+use reqwest::{header::HeaderMap, Response};
 use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, RequestBuilder};
-use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
+use reqwest_retry::policies::ExponentialBackoff;
 use reqwest_tracing::TracingMiddleware;
 use schemars::{schema_for, JsonSchema};
-use std::time::Duration;
-use tracing::error;
+use serde::{de::DeserializeOwned, Serialize};
+use sse::SseDecoder;
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+use tracing::{error, field, info_span, Instrument, Span};
 
 const TIMEOUT: u64 = 60;
 const MAX_RETRIES: u32 = 3;
+const MIN_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+const CIRCUIT_BREAKER_COOLDOWN: Duration = Duration::from_secs(30);
 
+/// The response fields the SDK doesn't model, collected when the `extra-fields` feature is enabled.
+#[cfg(feature = "extra-fields")]
+pub type ExtraFields = std::collections::HashMap<String, serde_json::Value>;
+
+/// The client of an OpenAI compatible API. `LlmSdk` is `Send + Sync` and cheap to clone: clones share the same
+/// HTTP client and thus its connection pool, so store one in the app state and clone it into each handler or task.
 #[derive(Debug, Clone, Builder)]
+#[builder(build_fn(validate = "Self::validate"))]
 pub struct LlmSdk {
-    #[builder(setter(into), default = r#""https://api.openai.com/v1".into()"#)]
+    /// The base url of the API. Trailing slashes are stripped, e.g. `https://api.openai.com/v1/` works as well.
+    #[builder(setter(custom), default = r#""https://api.openai.com/v1".into()"#)]
     pub(crate) base_url: String,
     #[builder(setter(into))]
     pub(crate) token: String,
+    /// The organization used for requests, sent as the `OpenAI-Organization` header.
+    #[builder(default, setter(strip_option, into))]
+    pub(crate) organization: Option<String>,
+    /// The project used for requests, sent as the `OpenAI-Project` header.
+    #[builder(default, setter(strip_option, into))]
+    pub(crate) project: Option<String>,
+    /// The Azure OpenAI API version. If set, requests are sent in Azure mode: the token is sent as the `api-key`
+    /// header and `api-version` is appended to the query. The base url shall point to the deployment, e.g.
+    /// `https://{resource}.openai.azure.com/openai/deployments/{deployment}`. See `LlmSdk::new_azure`.
+    #[builder(default, setter(strip_option, into))]
+    pub(crate) azure_api_version: Option<String>,
+    /// The maximum number of retries for transient failures. Defaults to 3.
     #[allow(dead_code)]
-    #[builder(default = "3")]
+    #[builder(default = "MAX_RETRIES")]
     pub(crate) max_retries: u32,
-    #[builder(setter(skip), default = "self.default_client()")]
+    /// The minimum (base) interval of the exponential backoff between retries. Defaults to 1 second.
+    #[allow(dead_code)]
+    #[builder(default = "MIN_RETRY_INTERVAL")]
+    pub(crate) min_retry_interval: Duration,
+    /// The maximum interval between retries. Defaults to 30 seconds.
+    #[allow(dead_code)]
+    #[builder(default = "MAX_RETRY_INTERVAL")]
+    pub(crate) max_retry_interval: Duration,
+    /// The jitter applied to the retry intervals. Defaults to `Jitter::Full`.
+    #[allow(dead_code)]
+    #[builder(default = "Jitter::Full")]
+    pub(crate) retry_jitter: Jitter,
+    /// The timeout of each request, including reading the response body. Defaults to 60 seconds.
+    #[builder(default = "Duration::from_secs(TIMEOUT)")]
+    pub(crate) timeout: Duration,
+    /// The maximum number of idle connections kept per host. Defaults to reqwest's default (unlimited).
+    #[allow(dead_code)]
+    #[builder(default, setter(strip_option))]
+    pub(crate) pool_max_idle_per_host: Option<usize>,
+    /// How long idle connections are kept in the pool. Defaults to reqwest's default (90 seconds).
+    #[allow(dead_code)]
+    #[builder(default, setter(strip_option))]
+    pub(crate) pool_idle_timeout: Option<Duration>,
+    /// The timeout of establishing a connection. Unlike `timeout`, it doesn't cover sending the request.
+    #[allow(dead_code)]
+    #[builder(default, setter(strip_option))]
+    pub(crate) connect_timeout: Option<Duration>,
+    /// Use HTTP/2 without negotiation, for servers known to support it. Defaults to false.
+    #[allow(dead_code)]
+    #[builder(default)]
+    pub(crate) http2_prior_knowledge: bool,
+    /// Route the requests through this proxy, e.g. `Proxy::all("socks5://proxy:1080")`. The hosts in the `NO_PROXY`
+    /// environment variable bypass it.
+    #[allow(dead_code)]
+    #[builder(default, setter(strip_option))]
+    pub(crate) proxy: Option<Proxy>,
+    /// Use the proxies of the `HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY` and `NO_PROXY` environment variables.
+    /// Defaults to true; set it to false to always connect directly, unless `proxy` is set.
+    #[allow(dead_code)]
+    #[builder(default = "true")]
+    pub(crate) system_proxy: bool,
+    /// Additional trusted root certificates, e.g. the private CA of an internal gateway.
+    #[allow(dead_code)]
+    #[builder(default, setter(into))]
+    pub(crate) root_certificates: Vec<Certificate>,
+    /// The client certificate and key for mutual TLS, e.g. `Identity::from_pem(&pem)` with the PEM-encoded PKCS#8
+    /// key followed by the certificate chain.
+    #[allow(dead_code)]
+    #[builder(default, setter(strip_option))]
+    pub(crate) identity: Option<Identity>,
+    /// Open the circuit after this many consecutive failures (transport errors or 5xx responses, after retries),
+    /// rejecting requests with `LlmError::CircuitOpen` for `circuit_breaker_cooldown`. Disabled by default.
+    #[allow(dead_code)]
+    #[builder(default, setter(strip_option))]
+    pub(crate) circuit_breaker_threshold: Option<u32>,
+    /// How long the circuit stays open. Defaults to 30 seconds.
+    #[allow(dead_code)]
+    #[builder(default = "CIRCUIT_BREAKER_COOLDOWN")]
+    pub(crate) circuit_breaker_cooldown: Duration,
+    /// Record the HTTP interactions to a cassette file, or replay them without sending requests. See `Cassette`.
+    #[allow(dead_code)]
+    #[builder(default, setter(strip_option))]
+    pub(crate) cassette: Option<Cassette>,
+    /// Headers attached to every request, e.g. `Helicone-Auth` for a gateway. Headers set by the SDK itself
+    /// (such as the bearer `Authorization` header) take precedence over default headers with the same name.
+    #[allow(dead_code)]
+    #[builder(default)]
+    pub(crate) default_headers: HeaderMap,
+    /// The model used by `LlmSdk::chat`. Defaults to `ChatCompleteModel::default()`.
+    #[builder(default)]
+    pub(crate) default_model: ChatCompleteModel,
+    /// The HTTP client. Defaults to a client built from the settings above, with tracing and retries. Set it to
+    /// use a custom middleware stack, e.g. a mock transport returning canned responses in tests. A custom client
+    /// ignores `max_retries`, the retry intervals, `retry_jitter` and `default_headers`. Building the default client
+    /// fails with a `ValidationError` if the TLS settings are invalid, e.g. a malformed root certificate.
+    #[builder(default = "self.default_client()?")]
     pub(crate) client: ClientWithMiddleware,
 }
 
 pub trait IntoRequest {
     fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder;
+
+    /// The timeout of this request, overriding the timeout of the SDK.
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// A request to an arbitrary endpoint, see `LlmSdk::post_json` and `LlmSdk::get_json`.
+enum RawRequest {
+    Get(String),
+    Post(String, serde_json::Value),
+}
+
+impl IntoRequest for RawRequest {
+    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        let url = |path: String| format!("{}/{}", base_url, path.trim_start_matches('/'));
+        match self {
+            Self::Get(path) => client.get(url(path)),
+            Self::Post(path, body) => client.post(url(path)).json(&body),
+        }
+    }
 }
 
 /// For tool function. If you have a function that you want ChatGPT to call, you shall put
@@ -44,17 +187,82 @@ pub trait ToSchema: JsonSchema {
 }
 
 impl LlmSdkBuilder {
+    pub fn base_url(&mut self, base_url: impl Into<String>) -> &mut Self {
+        let base_url: String = base_url.into();
+        self.base_url = Some(base_url.trim_end_matches('/').to_string());
+        self
+    }
+
     // Private helper method with access to the builder struct.
-    fn default_client(&self) -> ClientWithMiddleware {
+    fn default_client(&self) -> Result<ClientWithMiddleware, String> {
         let retry_policy = ExponentialBackoff::builder()
+            .retry_bounds(
+                self.min_retry_interval.unwrap_or(MIN_RETRY_INTERVAL),
+                self.max_retry_interval.unwrap_or(MAX_RETRY_INTERVAL),
+            )
+            .jitter(self.retry_jitter.unwrap_or(Jitter::Full))
             .build_with_max_retries(self.max_retries.unwrap_or(MAX_RETRIES));
-        let m = RetryTransientMiddleware::new_with_policy(retry_policy);
-        ClientBuilder::new(reqwest::Client::new())
-            // Trace HTTP requests. See the tracing crate to make use of these traces.
-            .with(TracingMiddleware::default())
-            // Retry failed requests.
-            .with(RetryMiddleware::from(m))
+        let mut client = reqwest::Client::builder()
+            .default_headers(self.default_headers.clone().unwrap_or_default());
+        if let Some(Some(max)) = self.pool_max_idle_per_host {
+            client = client.pool_max_idle_per_host(max);
+        }
+        if let Some(Some(timeout)) = self.pool_idle_timeout {
+            client = client.pool_idle_timeout(timeout);
+        }
+        if let Some(Some(timeout)) = self.connect_timeout {
+            client = client.connect_timeout(timeout);
+        }
+        if self.http2_prior_knowledge.unwrap_or_default() {
+            client = client.http2_prior_knowledge();
+        }
+        for cert in self.root_certificates.iter().flatten() {
+            client = client.add_root_certificate(cert.clone());
+        }
+        if let Some(Some(identity)) = &self.identity {
+            client = client.identity(identity.clone());
+        }
+        if !self.system_proxy.unwrap_or(true) {
+            client = client.no_proxy();
+        }
+        if let Some(Some(proxy)) = &self.proxy {
+            client = client.proxy(proxy.clone().no_proxy(reqwest::NoProxy::from_env()));
+        }
+        let client = client
             .build()
+            .map_err(|e| format!("failed to build the HTTP client: {}", e))?;
+        let mut client = ClientBuilder::new(client)
+            // Trace HTTP requests. See the tracing crate to make use of these traces.
+            .with(TracingMiddleware::default());
+        // Fail fast during outages. It wraps the retries, so that a request only counts as failure once retried.
+        if let Some(Some(threshold)) = self.circuit_breaker_threshold {
+            let cooldown = self
+                .circuit_breaker_cooldown
+                .unwrap_or(CIRCUIT_BREAKER_COOLDOWN);
+            client = client.with(CircuitBreakerMiddleware::new(threshold, cooldown));
+        }
+        // Retry failed requests.
+        let client = client.with(RetryMiddleware::from(retry_policy));
+        // Record or replay the actual HTTP calls, i.e. each retry.
+        Ok(match self.cassette.clone().flatten() {
+            Some(cassette) => client.with(cassette).build(),
+            None => client.build(),
+        })
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let min = self.min_retry_interval.unwrap_or(MIN_RETRY_INTERVAL);
+        let max = self.max_retry_interval.unwrap_or(MAX_RETRY_INTERVAL);
+        if min > max {
+            return Err(format!(
+                "min_retry_interval ({:?}) must not be greater than max_retry_interval ({:?})",
+                min, max
+            ));
+        }
+        if let Some(Some(0)) = self.circuit_breaker_threshold {
+            return Err("circuit_breaker_threshold must be greater than 0".into());
+        }
+        Ok(())
     }
 }
 
@@ -71,99 +279,717 @@ impl LlmSdk {
             .unwrap()
     }
 
+    /// Create an SDK sending requests with a pre-built client, e.g. one with a mock middleware for tests.
+    pub fn with_client(token: impl Into<String>, client: ClientWithMiddleware) -> Self {
+        LlmSdkBuilder::default()
+            .token(token)
+            .client(client)
+            .build()
+            .unwrap()
+    }
+
+    /// Create an SDK from the standard environment variables: `OPENAI_API_KEY` (required), and optionally
+    /// `OPENAI_BASE_URL` (e.g. for a local gateway), `OPENAI_ORG_ID` and `OPENAI_PROJECT_ID`.
+    pub fn from_env() -> Result<Self, LlmError> {
+        Self::from_env_with(|name| std::env::var(name).ok())
+    }
+
+    fn from_env_with(var: impl Fn(&str) -> Option<String>) -> Result<Self, LlmError> {
+        let var = |name| var(name).filter(|v: &String| !v.is_empty());
+        let token = var("OPENAI_API_KEY")
+            .ok_or_else(|| LlmError::Config("OPENAI_API_KEY is not set".into()))?;
+        let mut builder = LlmSdkBuilder::default();
+        builder.token(token);
+        if let Some(base_url) = var("OPENAI_BASE_URL") {
+            builder.base_url(base_url);
+        }
+        if let Some(organization) = var("OPENAI_ORG_ID") {
+            builder.organization(organization);
+        }
+        if let Some(project) = var("OPENAI_PROJECT_ID") {
+            builder.project(project);
+        }
+        builder.build().map_err(|e| LlmError::Config(e.to_string()))
+    }
+
+    /// Create an SDK for an Azure OpenAI deployment, e.g.
+    /// `LlmSdk::new_azure("https://my-resource.openai.azure.com", "gpt-4", "2024-02-01", api_key)`.
+    pub fn new_azure(
+        endpoint: impl AsRef<str>,
+        deployment: impl AsRef<str>,
+        api_version: impl Into<String>,
+        api_key: impl Into<String>,
+    ) -> Self {
+        let base_url = format!(
+            "{}/openai/deployments/{}",
+            endpoint.as_ref().trim_end_matches('/'),
+            deployment.as_ref()
+        );
+        LlmSdkBuilder::default()
+            .token(api_key)
+            .base_url(base_url)
+            .azure_api_version(api_version)
+            .build()
+            .unwrap()
+    }
+
+    /// Create a chat completion. Image parts are rejected with `LlmError::Misuse` if the model doesn't support them,
+    /// see `ChatCompleteModel::supports_vision`.
     pub async fn chat_completion(
         &self,
         req: ChatCompletionRequest,
-    ) -> Result<ChatCompletionResponse> {
-        assert!(!req.stream.unwrap_or_default());
+    ) -> Result<ChatCompletionResponse, LlmError> {
+        let (ret, _) = self.chat_completion_with_meta(req).await?;
+        Ok(ret)
+    }
+
+    /// Like `chat_completion`, but also return the metadata of the response, e.g. its `x-request-id`. See `with_meta`
+    /// for the other methods.
+    pub async fn chat_completion_with_meta(
+        &self,
+        mut req: ChatCompletionRequest,
+    ) -> Result<(ChatCompletionResponse, ResponseMeta), LlmError> {
+        check_vision(&req)?;
+        req.stream = Some(false);
+        let span = llm_span(
+            "chat_completion",
+            Some(&model_id(req.model())),
+            Some(req.messages().len()),
+        );
+        let start = Instant::now();
         let req = self.prepare_request(req);
-        let res = req.send_and_log().await?;
-        Ok(res.json::<ChatCompletionResponse>().await?)
+        let ret: Result<(ChatCompletionResponse, _), _> =
+            req.send_parse().instrument(span.clone()).await;
+        record_usage(&span, start, ret.as_ref().ok().map(|(res, _)| &res.usage));
+        ret
+    }
+
+    /// Create a chat completion for the messages with the default model of the SDK. Use `chat_completion` to
+    /// pick the model (or any other parameter) per request. Empty `messages` are rejected with `LlmError::Validation`.
+    pub async fn chat(
+        &self,
+        messages: Vec<ChatCompletionMessage>,
+    ) -> Result<ChatCompletionResponse, LlmError> {
+        let req = ChatCompletionRequest::try_new(self.default_model.clone(), messages)?;
+        self.chat_completion(req).await
     }
 
+    /// Drive the function calling loop: call the model, dispatch its tool calls with `registry` and send the
+    /// results back, until the model replies without tool calls. Failed tool calls (unknown tools, invalid
+    /// arguments or handler errors) are reported to the model as the tool result, so that it can correct itself.
+    /// If the request has no tools, the tools of the registry are used. Returns `LlmError::MaxTurnsExceeded` if
+    /// the model still calls tools after `max_turns` completions.
+    pub async fn run_with_tools(
+        &self,
+        req: ChatCompletionRequest,
+        registry: &mut ToolRegistry,
+        max_turns: usize,
+    ) -> Result<AssistantMessage, LlmError> {
+        self.run_with_tools_cancellable(req, registry, max_turns, future::pending())
+            .await
+    }
+
+    /// Like `run_with_tools`, but stop with `LlmError::Cancelled` once `cancel` resolves, e.g.
+    /// `token.cancelled()` of a tokio-util `CancellationToken`. The in-flight completion is dropped, and no tool is
+    /// dispatched after the cancellation.
+    pub async fn run_with_tools_cancellable(
+        &self,
+        mut req: ChatCompletionRequest,
+        registry: &mut ToolRegistry,
+        max_turns: usize,
+        cancel: impl Future<Output = ()>,
+    ) -> Result<AssistantMessage, LlmError> {
+        pin_mut!(cancel);
+        if req.tools.is_empty() {
+            req.tools = registry.tools();
+        }
+        for _ in 0..max_turns {
+            let res = with_cancellation(self.chat_completion(req.clone()), cancel.as_mut()).await?;
+            let message = res
+                .best_choice()
+                .map(|choice| choice.message.clone())
+                .ok_or_else(|| LlmError::Decode("the response has no choices".into()))?;
+            if message.tool_calls.is_empty() {
+                return Ok(message);
+            }
+            let mut tool_messages = Vec::with_capacity(message.tool_calls.len());
+            for tool_call in &message.tool_calls {
+                let tool_message = match registry.dispatch(tool_call) {
+                    Ok(m) => m,
+                    Err(e @ (LlmError::Tool { .. } | LlmError::ToolArguments { .. })) => {
+                        ToolMessage::new(e.to_string(), &tool_call.id)
+                    }
+                    Err(e) => return Err(e),
+                };
+                tool_messages.push(tool_message.into());
+            }
+            req.messages.push(message.into());
+            req.messages.extend(tool_messages);
+        }
+        Err(LlmError::MaxTurnsExceeded(max_turns))
+    }
+
+    /// Stream a chat completion, calling `f` with each chunk. `stream` is always set to true, regardless of the
+    /// request, while `chat_completion` always sets it to false. Image parts are rejected like in `chat_completion`.
     pub async fn chat_stream(
         &self,
         req: ChatCompletionRequest,
+        f: impl FnMut(&ChatStreamResponse),
+    ) -> Result<(), LlmError> {
+        self.chat_stream_cancellable(req, future::pending(), f)
+            .await
+    }
+
+    /// Like `chat_stream`, but stop with `LlmError::Cancelled` once `cancel` resolves. It is checked while waiting
+    /// for the response and between chunks, so `f` is not called after the cancellation.
+    pub async fn chat_stream_cancellable(
+        &self,
+        req: ChatCompletionRequest,
+        cancel: impl Future<Output = ()>,
         mut f: impl FnMut(&ChatStreamResponse),
-    ) -> Result<()> {
-        assert!(req.stream.unwrap_or_default());
+    ) -> Result<(), LlmError> {
+        let span = llm_span(
+            "chat_stream",
+            Some(&model_id(req.model())),
+            Some(req.messages().len()),
+        );
+        let start = Instant::now();
+        let mut usage = None;
+        let ret = self
+            .send_chat_stream(req, cancel, |res| {
+                if res.usage.is_some() {
+                    usage = res.usage.clone();
+                }
+                f(res)
+            })
+            .instrument(span.clone())
+            .await;
+        record_usage(&span, start, usage.as_ref());
+        ret
+    }
+
+    async fn send_chat_stream(
+        &self,
+        mut req: ChatCompletionRequest,
+        cancel: impl Future<Output = ()>,
+        mut f: impl FnMut(&ChatStreamResponse),
+    ) -> Result<(), LlmError> {
+        check_vision(&req)?;
+        req.stream = Some(true);
         let req = self.prepare_request(req);
-        let res = req.send_and_log().await?;
+        pin_mut!(cancel);
+        let res = with_cancellation(req.send_and_log(), cancel.as_mut()).await?;
+        for_each_sse_data(res, cancel, |data| {
+            let r: ChatStreamResponse =
+                serde_json::from_str(data).map_err(|source| LlmError::Deserialize {
+                    source,
+                    body: data.to_string(),
+                })?;
+            f(&r);
+            Ok(())
+        })
+        .await
+    }
 
-        let mut stream = res
-            .bytes_stream()
-            .filter(|i| future::ready(i.is_ok()))
-            .map(|i| {
-                let s = String::from_utf8(i.unwrap().to_vec()).unwrap();
-                s.split("\n\n")
-                    .map(|ss| ss.strip_prefix("data: "))
-                    .filter(|ss| matches!(ss, Some(sss) if !sss.is_empty() && "[DONE]" != *sss))
-                    .map(|ss| serde_json::from_str(ss.unwrap()).unwrap())
-                    .collect::<Vec<ChatStreamResponse>>()
-            });
-        while let Some(r) = stream.next().await {
-            r.iter().for_each(&mut f);
+    /// Create a completion with the legacy completions endpoint, e.g. a fill-in-the-middle completion.
+    pub async fn completion(&self, req: CompletionRequest) -> Result<CompletionResponse, LlmError> {
+        let span = llm_span("completion", Some(&model_id(req.model())), Some(1));
+        let start = Instant::now();
+        let req = self.prepare_request(req);
+        let ret: Result<(CompletionResponse, _), _> =
+            req.send_parse().instrument(span.clone()).await;
+        record_usage(&span, start, ret.as_ref().ok().map(|(res, _)| &res.usage));
+        Ok(ret?.0)
+    }
+
+    /// Stream a completion of the legacy completions endpoint, calling `f` with each chunk.
+    pub async fn completion_stream(
+        &self,
+        mut req: CompletionRequest,
+        mut f: impl FnMut(&CompletionStreamResponse),
+    ) -> Result<(), LlmError> {
+        let span = llm_span("completion_stream", Some(&model_id(req.model())), Some(1));
+        let start = Instant::now();
+        let mut usage = None;
+        req.stream = Some(true);
+        let req = self.prepare_request(req);
+        let ret = async {
+            let res = req.send_and_log().await?;
+            for_each_sse_data(res, future::pending(), |data| {
+                let chunk: CompletionStreamResponse =
+                    serde_json::from_str(data).map_err(|source| LlmError::Deserialize {
+                        source,
+                        body: data.to_string(),
+                    })?;
+                if chunk.usage.is_some() {
+                    usage = chunk.usage.clone();
+                }
+                f(&chunk);
+                Ok(())
+            })
+            .await
         }
-        Ok(())
+        .instrument(span.clone())
+        .await;
+        record_usage(&span, start, usage.as_ref());
+        ret
     }
 
-    pub async fn create_image(&self, req: CreateImageRequest) -> Result<CreateImageResponse> {
+    /// Like `chat_stream`, but also accumulate the chunks and return the assistant message of the first choice. If
+    /// the stream breaks off after some chunks, `LlmError::StreamInterrupted` carries the partial message, so that
+    /// e.g. a chat UI can keep what was displayed.
+    pub async fn chat_stream_message(
+        &self,
+        req: ChatCompletionRequest,
+        f: impl FnMut(&ChatStreamResponse),
+    ) -> Result<AssistantMessage, LlmError> {
+        self.chat_stream_message_cancellable(req, future::pending(), f)
+            .await
+    }
+
+    /// Like `chat_stream_message`, but stop once `cancel` resolves, see `chat_stream_cancellable`. If some chunks
+    /// already arrived, `LlmError::StreamInterrupted` carries the partial message with `LlmError::Cancelled` as its
+    /// source.
+    pub async fn chat_stream_message_cancellable(
+        &self,
+        req: ChatCompletionRequest,
+        cancel: impl Future<Output = ()>,
+        mut f: impl FnMut(&ChatStreamResponse),
+    ) -> Result<AssistantMessage, LlmError> {
+        let mut acc = ChatStreamAccumulator::new();
+        let ret = self
+            .chat_stream_cancellable(req, cancel, |res| {
+                acc.push(res);
+                f(res)
+            })
+            .await;
+        match ret {
+            Ok(()) => Ok(acc.finish()),
+            Err(e) if !acc.is_empty() => Err(LlmError::StreamInterrupted {
+                partial: Box::new(acc.finish()),
+                source: Box::new(e),
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Like `chat_stream`, but call `f` with each delta and the index of its choice, so that the N completions of a
+    /// request with n > 1 can be reconstructed independently.
+    pub async fn chat_stream_by_index(
+        &self,
+        req: ChatCompletionRequest,
+        mut f: impl FnMut(usize, &Delta),
+    ) -> Result<(), LlmError> {
+        self.chat_stream(req, |res| {
+            for (index, delta) in res.deltas() {
+                f(index, delta);
+            }
+        })
+        .await
+    }
+
+    pub async fn create_image(
+        &self,
+        req: CreateImageRequest,
+    ) -> Result<CreateImageResponse, LlmError> {
+        let span = llm_span("create_image", Some(&model_id(req.model())), None);
         let req = self.prepare_request(req);
-        let res = req.send_and_log().await?;
-        Ok(res.json::<CreateImageResponse>().await?)
+        let (ret, _) = traced(span, req.send_parse()).await?;
+        Ok(ret)
     }
 
-    pub async fn speech(&self, req: SpeechRequest) -> Result<Bytes> {
+    /// Generate an image and call `f` with each event as it arrives: the partial images (see
+    /// `CreateImageRequestBuilder::partial_images`) and then the final image. Only supported for gpt-image-1.
+    pub async fn create_image_stream(
+        &self,
+        mut req: CreateImageRequest,
+        mut f: impl FnMut(&CreateImageStreamEvent),
+    ) -> Result<(), LlmError> {
+        let span = llm_span("create_image_stream", Some(&model_id(req.model())), None);
+        req.stream = Some(true);
         let req = self.prepare_request(req);
-        let res = req.send_and_log().await?;
-        Ok(res.bytes().await?)
+        let fut = async {
+            let res = req.send_and_log().await?;
+            for_each_sse_data(res, future::pending(), |data| {
+                let event = serde_json::from_str(data).map_err(|source| LlmError::Deserialize {
+                    source,
+                    body: data.to_string(),
+                })?;
+                if let CreateImageStreamEvent::Completed {
+                    usage: Some(usage), ..
+                } = &event
+                {
+                    span.record("prompt_tokens", usage.input_tokens);
+                    span.record("completion_tokens", usage.output_tokens);
+                }
+                f(&event);
+                Ok(())
+            })
+            .await
+        };
+        traced(span.clone(), fut).await
     }
 
-    pub async fn whisper(&self, req: WhisperRequest) -> Result<WhisperResponse> {
-        let is_json = req.response_format == WhisperResponseFormat::Json;
+    /// Edit or extend an image given a prompt, e.g. inpainting the transparent areas of the mask.
+    pub async fn create_image_edit(
+        &self,
+        req: CreateImageEditRequest,
+    ) -> Result<CreateImageResponse, LlmError> {
+        let span = llm_span("create_image_edit", Some(&model_id(req.model())), None);
         let req = self.prepare_request(req);
-        let res = req.send_and_log().await?;
-        let ret = if is_json {
-            res.json::<WhisperResponse>().await?
-        } else {
-            let text = res.text().await?;
-            WhisperResponse { text }
+        let (ret, _) = traced(span, req.send_parse()).await?;
+        Ok(ret)
+    }
+
+    /// Create variations of an image.
+    pub async fn create_image_variation(
+        &self,
+        req: CreateImageVariationRequest,
+    ) -> Result<CreateImageResponse, LlmError> {
+        let span = llm_span("create_image_variation", Some(&model_id(req.model())), None);
+        let req = self.prepare_request(req);
+        let (ret, _) = traced(span, req.send_parse()).await?;
+        Ok(ret)
+    }
+
+    pub async fn speech(&self, req: SpeechRequest) -> Result<Bytes, LlmError> {
+        let span = llm_span("speech", Some(&model_id(req.model())), None);
+        let req = self.prepare_request(req);
+        traced(span, async { Ok(req.send_and_log().await?.bytes().await?) }).await
+    }
+
+    /// Generate audio like `speech`, but yield the audio chunks as they arrive instead of buffering the whole file,
+    /// so that playback can begin immediately.
+    pub async fn speech_stream(
+        &self,
+        req: SpeechRequest,
+    ) -> Result<impl Stream<Item = Result<Bytes, LlmError>>, LlmError> {
+        // the span ends once the response starts, as the caller drives the stream
+        let span = llm_span("speech_stream", Some(&model_id(req.model())), None);
+        let req = self.prepare_request(req);
+        let res = traced(span, req.send_and_log()).await?;
+        Ok(res.bytes_stream().map(|chunk| Ok(chunk?)))
+    }
+
+    pub async fn whisper(&self, req: WhisperRequest) -> Result<WhisperResponse, LlmError> {
+        let is_json = matches!(
+            req.response_format,
+            WhisperResponseFormat::Json | WhisperResponseFormat::VerboseJson
+        );
+        let operation = match req.request_type {
+            WhisperRequestType::Transcription => "transcription",
+            WhisperRequestType::Translation => "translation",
         };
+        let span = llm_span(operation, Some(&req.model().to_string()), None);
+        let req = self.prepare_request(req);
+        traced(span, async {
+            if is_json {
+                let (ret, _) = req.send_parse().await?;
+                return Ok(ret);
+            }
+            let text = req.send_and_log().await?.text().await?;
+            Ok(WhisperResponse {
+                text,
+                ..Default::default()
+            })
+        })
+        .await
+    }
+
+    /// Translate audio into English. This always posts to the translations endpoint, regardless of the request type.
+    pub async fn translate(&self, mut req: WhisperRequest) -> Result<WhisperResponse, LlmError> {
+        req.request_type = WhisperRequestType::Translation;
+        self.whisper(req).await
+    }
+
+    /// Embed the input. For batched input, `data` is ordered by `index`, i.e. the same order as the input.
+    pub async fn embedding(&self, req: EmbeddingRequest) -> Result<EmbeddingResponse, LlmError> {
+        let span = llm_span("embedding", Some(&model_id(req.model())), None);
+        let req = self.prepare_request(req);
+        let (mut ret, _): (EmbeddingResponse, _) = traced(span.clone(), req.send_parse()).await?;
+        span.record("prompt_tokens", ret.usage.prompt_tokens);
+        ret.data.sort_by_key(|d| d.index);
+        Ok(ret)
+    }
+
+    pub async fn upload_file(&self, req: UploadFileRequest) -> Result<FileObject, LlmError> {
+        let span = llm_span("upload_file", None, None);
+        let req = self.prepare_request(req);
+        let (ret, _) = traced(span, req.send_parse()).await?;
+        Ok(ret)
+    }
+
+    pub async fn list_files(&self) -> Result<FileList, LlmError> {
+        let span = llm_span("list_files", None, None);
+        let req = self.prepare_request(FileRequest::List);
+        let (ret, _) = traced(span, req.send_parse()).await?;
+        Ok(ret)
+    }
+
+    pub async fn retrieve_file(&self, id: &str) -> Result<FileObject, LlmError> {
+        let span = llm_span("retrieve_file", None, None);
+        let req = self.prepare_request(FileRequest::Retrieve(id.into()));
+        let (ret, _) = traced(span, req.send_parse()).await?;
+        Ok(ret)
+    }
+
+    pub async fn delete_file(&self, id: &str) -> Result<DeleteFileResponse, LlmError> {
+        let span = llm_span("delete_file", None, None);
+        let req = self.prepare_request(FileRequest::Delete(id.into()));
+        let (ret, _) = traced(span, req.send_parse()).await?;
         Ok(ret)
     }
 
-    pub async fn embedding(&self, req: EmbeddingRequest) -> Result<EmbeddingResponse> {
+    pub async fn retrieve_file_content(&self, id: &str) -> Result<Bytes, LlmError> {
+        let span = llm_span("retrieve_file_content", None, None);
+        let req = self.prepare_request(FileRequest::Content(id.into()));
+        traced(span, async { Ok(req.send_and_log().await?.bytes().await?) }).await
+    }
+
+    pub async fn create_batch(&self, req: CreateBatchRequest) -> Result<BatchObject, LlmError> {
+        let span = llm_span("create_batch", None, None);
         let req = self.prepare_request(req);
-        let res = req.send_and_log().await?;
-        Ok(res.json().await?)
+        let (ret, _) = traced(span, req.send_parse()).await?;
+        Ok(ret)
+    }
+
+    pub async fn retrieve_batch(&self, id: &str) -> Result<BatchObject, LlmError> {
+        let span = llm_span("retrieve_batch", None, None);
+        let req = self.prepare_request(BatchRequest::Retrieve(id.into()));
+        let (ret, _) = traced(span, req.send_parse()).await?;
+        Ok(ret)
+    }
+
+    pub async fn cancel_batch(&self, id: &str) -> Result<BatchObject, LlmError> {
+        let span = llm_span("cancel_batch", None, None);
+        let req = self.prepare_request(BatchRequest::Cancel(id.into()));
+        let (ret, _) = traced(span, req.send_parse()).await?;
+        Ok(ret)
+    }
+
+    pub async fn list_batches(&self) -> Result<BatchList, LlmError> {
+        let span = llm_span("list_batches", None, None);
+        let req = self.prepare_request(BatchRequest::List);
+        let (ret, _) = traced(span, req.send_parse()).await?;
+        Ok(ret)
+    }
+
+    /// POST `body` as JSON to `path` (relative to the base url, e.g. `/moderations`) and deserialize the response.
+    /// An escape hatch for endpoints the SDK doesn't support yet: the request goes through the same auth, retry
+    /// and logging as the typed methods.
+    pub async fn post_json<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: impl Serialize,
+    ) -> Result<T, LlmError> {
+        let body = serde_json::to_value(body).map_err(|e| LlmError::Validation(e.to_string()))?;
+        let span = llm_span("post_json", None, None);
+        let req = self.prepare_request(RawRequest::Post(path.to_string(), body));
+        let (ret, _) = traced(span, req.send_parse()).await?;
+        Ok(ret)
+    }
+
+    /// GET `path` (relative to the base url, e.g. `/models`) and deserialize the response. See `post_json`.
+    pub async fn get_json<T: DeserializeOwned>(&self, path: &str) -> Result<T, LlmError> {
+        let span = llm_span("get_json", None, None);
+        let req = self.prepare_request(RawRequest::Get(path.to_string()));
+        let (ret, _) = traced(span, req.send_parse()).await?;
+        Ok(ret)
     }
 
     fn prepare_request(&self, req: impl IntoRequest) -> RequestBuilder {
+        let timeout = req.timeout().unwrap_or(self.timeout);
         let req = req.into_request(&self.base_url, self.client.clone());
-        let req = if self.token.is_empty() {
-            req
-        } else {
-            req.bearer_auth(&self.token)
+        let req = match &self.azure_api_version {
+            Some(api_version) => {
+                let req = req.query(&[("api-version", api_version)]);
+                if self.token.is_empty() {
+                    req
+                } else {
+                    req.header("api-key", &self.token)
+                }
+            }
+            None if self.token.is_empty() => req,
+            None => req.bearer_auth(&self.token),
         };
-        req.timeout(Duration::from_secs(TIMEOUT))
+        let req = match &self.organization {
+            Some(organization) => req.header("OpenAI-Organization", organization),
+            None => req,
+        };
+        let req = match &self.project {
+            Some(project) => req.header("OpenAI-Project", project),
+            None => req,
+        };
+        req.timeout(timeout)
     }
 }
 
 trait SendAndLog {
-    async fn send_and_log(self) -> Result<Response>;
+    /// Send the request, turning non-success responses into `LlmError::Api`.
+    async fn send_and_log(self) -> Result<Response, LlmError>;
+    /// Send the request and deserialize the JSON body, capturing the response metadata before the body is consumed.
+    async fn send_parse<T: DeserializeOwned>(self) -> Result<(T, ResponseMeta), LlmError>;
 }
 
 impl SendAndLog for RequestBuilder {
-    async fn send_and_log(self) -> Result<Response> {
-        let res = self.send().await?;
+    async fn send_and_log(self) -> Result<Response, LlmError> {
+        let res = self.send().await.map_err(|e| match e {
+            reqwest_middleware::Error::Middleware(e) => match e.downcast_ref::<CircuitOpen>() {
+                Some(CircuitOpen(retry_in)) => LlmError::CircuitOpen {
+                    retry_in: *retry_in,
+                },
+                None => LlmError::Transport(e.into()),
+            },
+            e => LlmError::Transport(e),
+        })?;
         let status = res.status();
         if status.is_client_error() || status.is_server_error() {
+            let request_id = meta::request_id(res.headers());
+            let retry_after = middleware::suggested_delay(&res);
             let text = res.text().await?;
-            error!("API failed: {}", text);
-            return Err(anyhow!("API failed: {}", text));
+            error!("API failed (request id: {:?}): {}", request_id, text);
+            return Err(LlmError::Api {
+                status: status.as_u16(),
+                error: Box::new(ApiErrorBody::parse(text)),
+                request_id,
+                retry_after,
+            });
         }
+        meta::capture(&res);
         Ok(res)
     }
+
+    async fn send_parse<T: DeserializeOwned>(self) -> Result<(T, ResponseMeta), LlmError> {
+        let res = self.send_and_log().await?;
+        let meta = ResponseMeta::new(&res);
+        let body = res.text().await?;
+        match serde_json::from_str(&body) {
+            Ok(ret) => Ok((ret, meta)),
+            Err(source) => {
+                error!("Failed to deserialize response: {}", body);
+                Err(LlmError::Deserialize { source, body })
+            }
+        }
+    }
+}
+
+/// Call `f` with the data of each server-sent event, skipping the final `[DONE]`. Events can span several chunks
+/// (e.g. the base64 payload of an image), so the body is buffered until an event is complete.
+async fn for_each_sse_data(
+    res: Response,
+    cancel: impl Future<Output = ()>,
+    mut f: impl FnMut(&str) -> Result<(), LlmError>,
+) -> Result<(), LlmError> {
+    let mut handle = |data: String| match data.as_str() {
+        "" | "[DONE]" => Ok(()),
+        data => f(data),
+    };
+    let mut stream = res.bytes_stream();
+    let mut decoder = SseDecoder::default();
+    pin_mut!(cancel);
+    loop {
+        let chunk = match future::select(stream.next(), cancel.as_mut()).await {
+            Either::Left((Some(chunk), _)) => chunk?,
+            Either::Left((None, _)) => break,
+            Either::Right(_) => return Err(LlmError::Cancelled),
+        };
+        for data in decoder.push(&chunk)? {
+            if cancel.as_mut().now_or_never().is_some() {
+                return Err(LlmError::Cancelled);
+            }
+            handle(data)?;
+        }
+    }
+    match decoder.finish()? {
+        Some(data) => handle(data),
+        None => Ok(()),
+    }
+}
+
+/// Reject image parts for models known to lack vision, which the API would reject with an opaque error. `Other`
+/// models are let through, as their capability is unknown.
+fn check_vision(req: &ChatCompletionRequest) -> Result<(), LlmError> {
+    let model = req.model();
+    if req.has_images() && !matches!(model, ChatCompleteModel::Other(_)) && !model.supports_vision()
+    {
+        return Err(LlmError::Misuse(format!(
+            "model {} doesn't support image inputs, use a vision model such as gpt-4o",
+            model
+        )));
+    }
+    Ok(())
+}
+
+/// The span around an SDK call, following the OpenTelemetry GenAI naming where possible. The model and the number of
+/// messages are recorded for the calls that have them, the token usage and the latency once the response arrives,
+/// see `traced` and `record_usage`. The HTTP spans of `TracingMiddleware` are its children.
+fn llm_span(operation: &'static str, model: Option<&str>, messages: Option<usize>) -> Span {
+    let span = info_span!(
+        "llm",
+        otel.name = operation,
+        model = field::Empty,
+        messages,
+        prompt_tokens = field::Empty,
+        completion_tokens = field::Empty,
+        latency_ms = field::Empty,
+    );
+    if let Some(model) = model {
+        span.record("model", field::display(model));
+    }
+    span
+}
+
+/// The model id as sent to the API, e.g. gpt-3.5-turbo-1106 for `ChatCompleteModel::Gpt3Turbo` and the id itself for
+/// `Other` models, unlike the display name.
+fn model_id(model: &impl Serialize) -> String {
+    match serde_json::to_value(model) {
+        Ok(serde_json::Value::String(id)) => id,
+        _ => String::new(),
+    }
+}
+
+/// Run an SDK call in `span`, recording its latency.
+async fn traced<T>(
+    span: Span,
+    fut: impl Future<Output = Result<T, LlmError>>,
+) -> Result<T, LlmError> {
+    let start = Instant::now();
+    let ret = fut.instrument(span.clone()).await;
+    span.record("latency_ms", start.elapsed().as_millis() as u64);
+    ret
+}
+
+fn record_usage(span: &Span, start: Instant, usage: Option<&ChatCompleteUsage>) {
+    span.record("latency_ms", start.elapsed().as_millis() as u64);
+    if let Some(usage) = usage {
+        span.record("prompt_tokens", usage.prompt_tokens);
+        span.record("completion_tokens", usage.completion_tokens);
+    }
+}
+
+/// Convert a Unix timestamp (in seconds) of a response.
+#[cfg(feature = "chrono")]
+pub(crate) fn unix_timestamp(secs: u64) -> chrono::DateTime<chrono::Utc> {
+    i64::try_from(secs)
+        .ok()
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+        .unwrap_or_default()
+}
+
+/// Run an SDK call until `cancel` resolves, e.g. `with_cancellation(sdk.chat_completion(req), token.cancelled())`
+/// with a tokio-util `CancellationToken`. If `cancel` resolves first, the in-flight request is dropped, which tears
+/// down its connection, and `LlmError::Cancelled` is returned. For streams and the function calling loop, prefer
+/// the `_cancellable` methods, e.g. `LlmSdk::chat_stream_message_cancellable`, which keep what already arrived.
+pub async fn with_cancellation<T>(
+    fut: impl Future<Output = Result<T, LlmError>>,
+    cancel: impl Future<Output = ()>,
+) -> Result<T, LlmError> {
+    pin_mut!(fut);
+    pin_mut!(cancel);
+    match future::select(fut, cancel).await {
+        Either::Left((ret, _)) => ret,
+        Either::Right(_) => Err(LlmError::Cancelled),
+    }
 }
 
 impl<T: JsonSchema> ToSchema for T {
@@ -179,5 +1005,1658 @@ fn init() {
 
 #[cfg(test)]
 lazy_static::lazy_static! {
-    static ref SDK: LlmSdk = LlmSdk::new(std::env::var("OPENAI_API_KEY").unwrap());
+    static ref SDK: LlmSdk = LlmSdk::from_env().unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{
+        matchers::{
+            body_partial_json, body_string_contains, header, header_exists, method, path,
+            query_param,
+        },
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    fn embedding_response() -> serde_json::Value {
+        serde_json::json!({
+            "object": "list",
+            "data": [{ "object": "embedding", "index": 0, "embedding": [0.1, 0.2] }],
+            "model": "text-embedding-ada-002-v2",
+            "usage": { "prompt_tokens": 1, "total_tokens": 1 }
+        })
+    }
+
+    #[tokio::test]
+    async fn embedding_data_should_be_ordered_by_index() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "object": "list",
+                "data": [
+                    { "object": "embedding", "index": 1, "embedding": [1.0] },
+                    { "object": "embedding", "index": 0, "embedding": [0.0] }
+                ],
+                "model": "text-embedding-ada-002-v2",
+                "usage": { "prompt_tokens": 2, "total_tokens": 2 }
+            })))
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let req = EmbeddingRequest::new_array(vec!["a".into(), "b".into()]);
+        let res = sdk.embedding(req).await.unwrap();
+        assert_eq!(res.data[0].index, 0);
+        assert_eq!(res.data[1].index, 1);
+    }
+
+    #[tokio::test]
+    async fn deserialize_error_should_keep_raw_body() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"result":"unexpected"}"#))
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let err = sdk
+            .embedding(EmbeddingRequest::new("hello"))
+            .await
+            .unwrap_err();
+        match err {
+            LlmError::Deserialize { body, .. } => assert_eq!(body, r#"{"result":"unexpected"}"#),
+            _ => panic!("unexpected error: {err:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn create_image_stream_should_yield_events() {
+        let partial = serde_json::json!({
+            "type": "image_generation.partial_image",
+            "b64_json": "cGFydGlhbA==",
+            "created_at": 1620000000,
+            "partial_image_index": 0
+        });
+        let completed = serde_json::json!({
+            "type": "image_generation.completed",
+            "b64_json": "ZmluYWw=",
+            "created_at": 1620000000
+        });
+        let body = format!(
+            "event: image_generation.partial_image\ndata: {partial}\n\nevent: image_generation.completed\ndata: {completed}\n\n"
+        );
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/images/generations"))
+            .and(body_partial_json(serde_json::json!({
+                "model": "gpt-image-1",
+                "stream": true,
+                "partial_images": 1
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let req = CreateImageRequestBuilder::default()
+            .prompt("a cute caterpillar")
+            .model(ImageModel::GptImage1)
+            .partial_images(1)
+            .build()
+            .unwrap();
+        let mut events = Vec::new();
+        sdk.create_image_stream(req, |event| events.push(event.b64_json().to_string()))
+            .await
+            .unwrap();
+        assert_eq!(events, vec!["cGFydGlhbA==", "ZmluYWw="]);
+    }
+
+    #[tokio::test]
+    async fn create_image_edit_should_post_multipart() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/images/edits"))
+            .and(body_string_contains("name=\"mask\""))
+            .and(body_string_contains("add a hat"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "created": 1589478378,
+                "data": [{ "url": "https://example.com/image.png" }]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/images/variations"))
+            .and(body_string_contains("256x256"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "created": 1589478378,
+                "data": [{ "b64_json": "aGVsbG8=" }]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let req = CreateImageEditRequestBuilder::default()
+            .image(b"image".to_vec())
+            .mask(b"mask".to_vec())
+            .prompt("add a hat")
+            .build()
+            .unwrap();
+        let res = sdk.create_image_edit(req).await.unwrap();
+        assert_eq!(res.data.len(), 1);
+
+        let req = CreateImageVariationRequestBuilder::default()
+            .image(b"image".to_vec())
+            .size(ImageSize::Small)
+            .build()
+            .unwrap();
+        let res = sdk.create_image_variation(req).await.unwrap();
+        assert_eq!(res.decode().unwrap(), vec![Bytes::from("hello")]);
+    }
+
+    #[tokio::test]
+    async fn post_json_and_get_json_should_work() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/moderations"))
+            .and(header("Authorization", "Bearer token"))
+            .and(body_partial_json(serde_json::json!({ "input": "hello" })))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({ "flagged": false })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/models"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "object": "list",
+                "data": []
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let res: serde_json::Value = sdk
+            .post_json("/moderations", serde_json::json!({ "input": "hello" }))
+            .await
+            .unwrap();
+        assert_eq!(res["flagged"], false);
+        let res: serde_json::Value = sdk.get_json("models").await.unwrap();
+        assert_eq!(res["object"], "list");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn cloned_sdk_should_be_shared_across_tasks() {
+        fn assert_send_sync_clone<T: Send + Sync + Clone + 'static>() {}
+        assert_send_sync_clone::<LlmSdk>();
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(embedding_response()))
+            .expect(8)
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let sdk = sdk.clone();
+                tokio::spawn(
+                    async move { sdk.embedding(EmbeddingRequest::new(format!("{i}"))).await },
+                )
+            })
+            .collect();
+        for handle in handles {
+            handle.await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn pool_settings_should_be_applied() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(embedding_response()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdkBuilder::default()
+            .token("token")
+            .base_url(server.uri())
+            .pool_max_idle_per_host(4)
+            .pool_idle_timeout(Duration::from_secs(30))
+            .connect_timeout(Duration::from_secs(5))
+            .build()
+            .unwrap();
+        sdk.embedding(EmbeddingRequest::new("hello")).await.unwrap();
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn gzip_should_be_accepted() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .and(|req: &wiremock::Request| {
+                req.headers
+                    .get(&"accept-encoding".into())
+                    .is_some_and(|v| v.as_str().contains("gzip"))
+            })
+            .respond_with(ResponseTemplate::new(200).set_body_json(embedding_response()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        sdk.embedding(EmbeddingRequest::new("hello")).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn circuit_breaker_should_fail_fast_after_consecutive_failures() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdkBuilder::default()
+            .token("token")
+            .base_url(server.uri())
+            .max_retries(0)
+            .circuit_breaker_threshold(2)
+            .circuit_breaker_cooldown(Duration::from_secs(60))
+            .build()
+            .unwrap();
+        for _ in 0..2 {
+            let err = sdk
+                .embedding(EmbeddingRequest::new("hello"))
+                .await
+                .unwrap_err();
+            assert!(matches!(err, LlmError::Api { status: 503, .. }));
+        }
+        let err = sdk
+            .embedding(EmbeddingRequest::new("hello"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, LlmError::CircuitOpen { .. }));
+    }
+
+    #[tokio::test]
+    async fn circuit_breaker_should_let_a_single_probe_through() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(embedding_response())
+                    .set_delay(Duration::from_millis(100)),
+            )
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdkBuilder::default()
+            .token("token")
+            .base_url(server.uri())
+            .max_retries(0)
+            .circuit_breaker_threshold(1)
+            .circuit_breaker_cooldown(Duration::from_millis(50))
+            .build()
+            .unwrap();
+        sdk.embedding(EmbeddingRequest::new("hello"))
+            .await
+            .unwrap_err();
+        tokio::time::sleep(Duration::from_millis(60)).await;
+
+        // half-open: only one of the concurrent requests probes
+        let (a, b) = tokio::join!(
+            sdk.embedding(EmbeddingRequest::new("hello")),
+            sdk.embedding(EmbeddingRequest::new("hello"))
+        );
+        assert_eq!(a.is_ok() as u8 + b.is_ok() as u8, 1);
+        let err = a.err().or(b.err()).unwrap();
+        assert!(matches!(err, LlmError::CircuitOpen { .. }));
+
+        // the successful probe closed the circuit
+        sdk.embedding(EmbeddingRequest::new("hello")).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn cassette_should_record_and_replay() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(embedding_response()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let file =
+            std::env::temp_dir().join(format!("llm-sdk-cassette-{}.json", std::process::id()));
+        let sdk = LlmSdkBuilder::default()
+            .token("secret-token")
+            .base_url(server.uri())
+            .cassette(Cassette::record(&file))
+            .build()
+            .unwrap();
+        let recorded = sdk.embedding(EmbeddingRequest::new("hello")).await.unwrap();
+        let content = std::fs::read_to_string(&file).unwrap();
+        assert!(!content.contains("secret-token"));
+
+        // replaying doesn't hit the server, which expects a single request
+        let sdk = LlmSdkBuilder::default()
+            .token("token")
+            .base_url("http://localhost:1")
+            .cassette(Cassette::replay(&file).unwrap())
+            .build()
+            .unwrap();
+        let replayed = sdk.embedding(EmbeddingRequest::new("hello")).await.unwrap();
+        assert_eq!(replayed.data[0].embedding, recorded.data[0].embedding);
+        let err = sdk.list_files().await.unwrap_err();
+        assert!(matches!(err, LlmError::Transport(_)));
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn cassette_should_replay_binary_bodies() {
+        // not valid UTF-8, e.g. the start of an MP3 frame
+        let audio = vec![0xff, 0xfb, 0x90, 0x64, 0x00, 0x0f, 0xf0];
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/files/file-123/content"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(audio.clone(), "audio/mpeg"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let file = std::env::temp_dir().join(format!(
+            "llm-sdk-cassette-binary-{}.json",
+            std::process::id()
+        ));
+        let sdk = LlmSdkBuilder::default()
+            .token("token")
+            .base_url(server.uri())
+            .cassette(Cassette::record(&file))
+            .build()
+            .unwrap();
+        let recorded = sdk.retrieve_file_content("file-123").await.unwrap();
+        assert_eq!(recorded, audio);
+
+        let sdk = LlmSdkBuilder::default()
+            .token("token")
+            .base_url("http://localhost:1")
+            .cassette(Cassette::replay(&file).unwrap())
+            .build()
+            .unwrap();
+        let replayed = sdk.retrieve_file_content("file-123").await.unwrap();
+        assert_eq!(replayed, audio);
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn requests_should_go_through_proxy() {
+        let proxy = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/embeddings"))
+            .and(header("host", "llm.internal"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(embedding_response()))
+            .expect(1)
+            .mount(&proxy)
+            .await;
+
+        let sdk = LlmSdkBuilder::default()
+            .token("token")
+            .base_url("http://llm.internal/v1")
+            .proxy(Proxy::http(proxy.uri()).unwrap())
+            .build()
+            .unwrap();
+        sdk.embedding(EmbeddingRequest::new("hello")).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn root_certificate_and_identity_should_be_accepted() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(embedding_response()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let ca = Certificate::from_pem(include_bytes!("../fixtures/ca.pem")).unwrap();
+        let identity = Identity::from_pem(include_bytes!("../fixtures/client.pem")).unwrap();
+        let sdk = LlmSdkBuilder::default()
+            .token("token")
+            .base_url(server.uri())
+            .root_certificates(vec![ca])
+            .identity(identity)
+            .build()
+            .unwrap();
+        sdk.embedding(EmbeddingRequest::new("hello")).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn organization_and_project_headers_should_be_sent() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .and(header("OpenAI-Organization", "org-123"))
+            .and(header("OpenAI-Project", "proj-456"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(embedding_response()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdkBuilder::default()
+            .token("token")
+            .base_url(server.uri())
+            .organization("org-123")
+            .project("proj-456")
+            .build()
+            .unwrap();
+        sdk.embedding(EmbeddingRequest::new("hello")).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn default_headers_should_be_sent() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .and(header("Helicone-Auth", "Bearer helicone"))
+            .and(header("Authorization", "Bearer token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(embedding_response()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Helicone-Auth", "Bearer helicone".parse().unwrap());
+        headers.insert("Authorization", "Bearer overridden".parse().unwrap());
+        let sdk = LlmSdkBuilder::default()
+            .token("token")
+            .base_url(server.uri())
+            .default_headers(headers)
+            .build()
+            .unwrap();
+        sdk.embedding(EmbeddingRequest::new("hello")).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn server_error_should_be_retried_max_retries_times() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdkBuilder::default()
+            .token("token")
+            .base_url(server.uri())
+            .max_retries(2)
+            .min_retry_interval(Duration::from_millis(1))
+            .max_retry_interval(Duration::from_millis(10))
+            .retry_jitter(Jitter::None)
+            .build()
+            .unwrap();
+        let err = sdk
+            .embedding(EmbeddingRequest::new("hello"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, LlmError::Api { status: 503, .. }));
+    }
+
+    #[tokio::test]
+    async fn stream_setup_should_honor_retry_after() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(429).insert_header("retry-after-ms", "300"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_raw("data: [DONE]\n\n", "text/event-stream"),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // the backoff alone would retry after 10 milliseconds
+        let sdk = LlmSdkBuilder::default()
+            .token("token")
+            .base_url(server.uri())
+            .min_retry_interval(Duration::from_millis(10))
+            .retry_jitter(Jitter::None)
+            .build()
+            .unwrap();
+        let req = ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4oMini,
+            vec![ChatCompletionMessage::new_user("Hi", "")],
+        );
+        let start = Instant::now();
+        sdk.chat_stream(req, |_| {}).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(300));
+    }
+
+    #[tokio::test]
+    async fn retry_after_should_be_exposed_on_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(429).insert_header("retry-after", "20"))
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdkBuilder::default()
+            .token("token")
+            .base_url(server.uri())
+            .max_retries(0)
+            .build()
+            .unwrap();
+        let req = ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4oMini,
+            vec![ChatCompletionMessage::new_user("Hi", "")],
+        );
+        let err = sdk.chat_stream(req, |_| {}).await.unwrap_err();
+        assert_eq!(err.retry_after(), Some(Duration::from_secs(20)));
+    }
+
+    #[tokio::test]
+    async fn image_parts_should_be_rejected_for_non_vision_models() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_raw("data: [DONE]\n\n", "text/event-stream"),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let messages = vec![ChatCompletionMessage::new_user_with_images(
+            "What is in the image?",
+            ["https://example.com/cat.png"],
+            "",
+        )];
+        let req = ChatCompletionRequest::new(ChatCompleteModel::Gpt3Turbo, messages.clone());
+        let err = sdk.chat_completion(req.clone()).await.unwrap_err();
+        assert!(matches!(err, LlmError::Misuse(_)));
+        let err = sdk.chat_stream(req, |_| {}).await.unwrap_err();
+        assert!(matches!(err, LlmError::Misuse(_)));
+
+        // the capability of other models is unknown
+        let req = ChatCompletionRequest::new(ChatCompleteModel::Other("llava".into()), messages);
+        sdk.chat_stream(req, |_| {}).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rate_limit_should_be_retried_after_suggested_delay() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .respond_with(ResponseTemplate::new(429).insert_header("retry-after-ms", "10"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(embedding_response()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // the backoff alone would wait at least 10 seconds
+        let sdk = LlmSdkBuilder::default()
+            .token("token")
+            .base_url(server.uri())
+            .min_retry_interval(Duration::from_secs(10))
+            .retry_jitter(Jitter::None)
+            .build()
+            .unwrap();
+        let start = std::time::Instant::now();
+        sdk.embedding(EmbeddingRequest::new("hello")).await.unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn bad_request_should_not_be_retried() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .respond_with(ResponseTemplate::new(400))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let err = sdk
+            .embedding(EmbeddingRequest::new("hello"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, LlmError::Api { status: 400, .. }));
+    }
+
+    #[test]
+    fn invalid_retry_bounds_should_fail() {
+        let ret = LlmSdkBuilder::default()
+            .token("token")
+            .min_retry_interval(Duration::from_secs(10))
+            .max_retry_interval(Duration::from_secs(1))
+            .build();
+        assert!(matches!(ret, Err(LlmSdkBuilderError::ValidationError(_))));
+    }
+
+    #[test]
+    fn invalid_root_certificate_should_fail() {
+        let ret = LlmSdkBuilder::default()
+            .token("token")
+            .root_certificates(vec![Certificate::from_der(b"not a certificate").unwrap()])
+            .build();
+        assert!(matches!(ret, Err(LlmSdkBuilderError::ValidationError(_))));
+    }
+
+    #[tokio::test]
+    async fn azure_request_should_use_deployment_url_and_api_key() {
+        let server = MockServer::start().await;
+        Mock::given(header_exists("Authorization"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/openai/deployments/my-embedding/embeddings"))
+            .and(query_param("api-version", "2024-02-01"))
+            .and(header("api-key", "azure-key"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(embedding_response()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_azure(
+            format!("{}/", server.uri()),
+            "my-embedding",
+            "2024-02-01",
+            "azure-key",
+        );
+        sdk.embedding(EmbeddingRequest::new("hello")).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn request_should_be_cancelled() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(embedding_response())
+                    .set_delay(Duration::from_secs(5)),
+            )
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let start = std::time::Instant::now();
+        let err = with_cancellation(
+            sdk.embedding(EmbeddingRequest::new("hello")),
+            tokio::time::sleep(Duration::from_millis(50)),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, LlmError::Cancelled));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn request_should_complete_when_not_cancelled() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(embedding_response()))
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let res = with_cancellation(
+            sdk.embedding(EmbeddingRequest::new("hello")),
+            future::pending(),
+        )
+        .await;
+        assert!(res.is_ok());
+    }
+
+    #[tokio::test]
+    async fn request_should_time_out() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(embedding_response())
+                    .set_delay(Duration::from_millis(500)),
+            )
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdkBuilder::default()
+            .token("token")
+            .base_url(server.uri())
+            .max_retries(0)
+            .timeout(Duration::from_millis(50))
+            .build()
+            .unwrap();
+        let err = sdk
+            .embedding(EmbeddingRequest::new("hello"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, LlmError::Transport(_)));
+    }
+
+    #[tokio::test]
+    async fn organization_and_project_headers_should_be_skipped_when_unset() {
+        let server = MockServer::start().await;
+        Mock::given(header_exists("OpenAI-Organization"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(embedding_response()))
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        sdk.embedding(EmbeddingRequest::new("hello")).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn api_error_should_be_parsed_from_envelope() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .respond_with(
+                ResponseTemplate::new(400)
+                    .insert_header("x-request-id", "req_123")
+                    .set_body_json(serde_json::json!({
+                        "error": {
+                            "message": "Invalid input",
+                            "type": "invalid_request_error",
+                            "code": "invalid_value",
+                            "param": "input"
+                        }
+                    })),
+            )
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let err = sdk
+            .embedding(EmbeddingRequest::new("hello"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.request_id(), Some("req_123"));
+        match err {
+            LlmError::Api {
+                status,
+                error,
+                request_id,
+                ..
+            } => {
+                assert_eq!(status, 400);
+                assert_eq!(request_id.as_deref(), Some("req_123"));
+                assert_eq!(
+                    *error,
+                    ApiErrorBody {
+                        message: "Invalid input".into(),
+                        r#type: Some("invalid_request_error".into()),
+                        code: Some("invalid_value".into()),
+                        param: Some("input".into()),
+                    }
+                );
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn api_error_should_fallback_to_raw_text() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .respond_with(ResponseTemplate::new(404).set_body_string("not found"))
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let err = sdk
+            .embedding(EmbeddingRequest::new("hello"))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            LlmError::Api { status: 404, ref error, request_id: None, .. } if error.message == "not found" && error.code.is_none()
+        ));
+    }
+
+    #[tokio::test]
+    async fn speech_stream_should_yield_audio() {
+        let server = MockServer::start().await;
+        let audio = vec![7u8; 64 * 1024];
+        Mock::given(method("POST"))
+            .and(path("/audio/speech"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(audio.clone()))
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let stream = sdk
+            .speech_stream(SpeechRequest::new("hello"))
+            .await
+            .unwrap();
+        pin_mut!(stream);
+        let mut received = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            received.extend_from_slice(&chunk.unwrap());
+        }
+        assert_eq!(received, audio);
+    }
+
+    /// Collect the fields of the `llm` spans as `name=value`.
+    #[derive(Clone, Default)]
+    struct SpanFields(std::sync::Arc<std::sync::Mutex<Vec<String>>>);
+
+    impl tracing::field::Visit for SpanFields {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("{}={:?}", field.name(), value));
+        }
+    }
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for SpanFields {
+        fn on_new_span(
+            &self,
+            attrs: &tracing::span::Attributes<'_>,
+            _: &tracing::span::Id,
+            _: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            if attrs.metadata().name() == "llm" {
+                attrs.record(&mut self.clone());
+            }
+        }
+
+        fn on_record(
+            &self,
+            _: &tracing::span::Id,
+            values: &tracing::span::Record<'_>,
+            _: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            values.record(&mut self.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn chat_completion_should_record_span_fields() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1702685778,
+                "model": "gpt-4o-mini",
+                "system_fingerprint": null,
+                "choices": [],
+                "usage": { "prompt_tokens": 9, "completion_tokens": 2, "total_tokens": 11 }
+            })))
+            .mount(&server)
+            .await;
+
+        let fields = SpanFields::default();
+        let subscriber = tracing_subscriber::registry().with(fields.clone());
+        let _guard = tracing::subscriber::set_default(subscriber);
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        sdk.chat(vec![ChatCompletionMessage::new_user("Hi", "")])
+            .await
+            .unwrap();
+
+        let fields = fields.0.lock().unwrap();
+        for expected in [
+            "model=gpt-4o-mini",
+            "messages=1",
+            "prompt_tokens=9",
+            "completion_tokens=2",
+        ] {
+            assert!(
+                fields.iter().any(|f| f == expected),
+                "{expected} not in {fields:?}"
+            );
+        }
+        assert!(fields.iter().any(|f| f.starts_with("latency_ms=")));
+    }
+
+    #[tokio::test]
+    async fn spans_should_record_model_ids() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1702685778,
+                "model": "deepseek-reasoner",
+                "system_fingerprint": null,
+                "choices": [],
+                "usage": { "prompt_tokens": 9, "completion_tokens": 2, "total_tokens": 11 }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(embedding_response()))
+            .mount(&server)
+            .await;
+
+        let fields = SpanFields::default();
+        let subscriber = tracing_subscriber::registry().with(fields.clone());
+        let _guard = tracing::subscriber::set_default(subscriber);
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        for model in [
+            ChatCompleteModel::Other("deepseek-reasoner".into()),
+            ChatCompleteModel::Gpt3Turbo,
+        ] {
+            let req =
+                ChatCompletionRequest::new(model, vec![ChatCompletionMessage::new_user("Hi", "")]);
+            sdk.chat_completion(req).await.unwrap();
+        }
+        sdk.embedding(EmbeddingRequest::new("hello")).await.unwrap();
+
+        let fields = fields.0.lock().unwrap();
+        for expected in [
+            "model=deepseek-reasoner",
+            "model=gpt-3.5-turbo-1106",
+            "model=text-embedding-ada-002",
+            "prompt_tokens=1",
+            "otel.name=\"embedding\"",
+        ] {
+            assert!(
+                fields.iter().any(|f| f == expected),
+                "{expected} not in {fields:?}"
+            );
+        }
+        assert!(!fields.iter().any(|f| f == "model=Other"));
+    }
+
+    #[tokio::test]
+    async fn request_timeout_should_override_sdk_timeout() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdkBuilder::default()
+            .token("token")
+            .base_url(server.uri())
+            .max_retries(0)
+            .build()
+            .unwrap();
+        let req = ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4oMini,
+            vec![ChatCompletionMessage::new_user("Hi", "")],
+        )
+        .with_timeout(Duration::from_millis(100));
+        let start = Instant::now();
+        let err = sdk.chat_completion(req).await.unwrap_err();
+        assert!(matches!(err, LlmError::Transport(_)));
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn stream_should_be_set_by_method() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .and(body_partial_json(serde_json::json!({ "stream": false })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1702685778,
+                "model": "gpt-4o-mini",
+                "system_fingerprint": null,
+                "choices": [],
+                "usage": { "prompt_tokens": 9, "completion_tokens": 0, "total_tokens": 9 }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .and(body_partial_json(serde_json::json!({ "stream": true })))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_raw("data: [DONE]\n\n", "text/event-stream"),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let messages = vec![ChatCompletionMessage::new_user("Hi", "")];
+        let req = ChatCompletionRequestBuilder::default()
+            .messages(messages.clone())
+            .stream(true)
+            .build()
+            .unwrap();
+        sdk.chat_completion(req).await.unwrap();
+
+        let req = ChatCompletionRequest::new(ChatCompleteModel::Gpt4oMini, messages);
+        sdk.chat_stream(req, |_| {}).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn interrupted_stream_should_keep_partial_message() {
+        let chunk = serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion.chunk",
+            "created": 1700000000,
+            "model": "gpt-4o-mini",
+            "system_fingerprint": null,
+            "choices": [{ "index": 0, "delta": { "role": "assistant", "content": "Once upon" }, "finish_reason": null }]
+        });
+        let body = format!("data: {chunk}\n\ndata: {{\"id\": \"chatcmpl-1\", \"choi\n\n");
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let req = ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4oMini,
+            vec![ChatCompletionMessage::new_user("Tell a story", "")],
+        );
+        let err = sdk.chat_stream_message(req, |_| {}).await.unwrap_err();
+        let partial = err.partial_message().unwrap();
+        assert_eq!(partial.content.as_deref(), Some("Once upon"));
+        assert!(matches!(
+            err,
+            LlmError::StreamInterrupted { ref source, .. } if matches!(**source, LlmError::Deserialize { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn cancelled_stream_should_keep_partial_message() {
+        let chunk = |content: &str| {
+            serde_json::json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion.chunk",
+                "created": 1700000000,
+                "model": "gpt-4o-mini",
+                "system_fingerprint": null,
+                "choices": [{ "index": 0, "delta": { "content": content }, "finish_reason": null }]
+            })
+        };
+        let body = format!(
+            "data: {}\n\ndata: {}\n\ndata: [DONE]\n\n",
+            chunk("Once upon"),
+            chunk(" a time")
+        );
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let req = ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4oMini,
+            vec![ChatCompletionMessage::new_user("Tell a story", "")],
+        );
+        // cancel once the first chunk arrived
+        let cancelled = std::cell::Cell::new(false);
+        let cancel = future::poll_fn(|_| {
+            if cancelled.get() {
+                std::task::Poll::Ready(())
+            } else {
+                std::task::Poll::Pending
+            }
+        });
+        let mut chunks = 0;
+        let err = sdk
+            .chat_stream_message_cancellable(req, cancel, |_| {
+                chunks += 1;
+                cancelled.set(true);
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(chunks, 1);
+        let partial = err.partial_message().unwrap();
+        assert_eq!(partial.content.as_deref(), Some("Once upon"));
+        assert!(matches!(
+            err,
+            LlmError::StreamInterrupted { ref source, .. } if matches!(**source, LlmError::Cancelled)
+        ));
+    }
+
+    #[tokio::test]
+    async fn chat_stream_should_skip_non_data_lines() {
+        let chunk = |content: &str| {
+            serde_json::json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion.chunk",
+                "created": 1700000000,
+                "model": "gpt-4o-mini",
+                "system_fingerprint": null,
+                "choices": [{ "index": 0, "delta": { "content": content }, "finish_reason": null }]
+            })
+        };
+        // what some gateways emit: comments, event, id and retry fields, and CRLF line endings
+        let body = format!(
+            ": OPENROUTER PROCESSING\r\n\r\nretry: 3000\r\nevent: message\r\nid: 1\r\ndata: {}\r\n\r\nid: 2\r\ndata: {}\r\n\r\ndata: [DONE]\r\n\r\n",
+            chunk("Hello"),
+            chunk(" world")
+        );
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let req = ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4oMini,
+            vec![ChatCompletionMessage::new_user("Hi", "")],
+        );
+        let message = sdk.chat_stream_message(req, |_| {}).await.unwrap();
+        assert_eq!(message.content.as_deref(), Some("Hello world"));
+    }
+
+    #[tokio::test]
+    async fn completion_stream_should_yield_text() {
+        let chunk = |text: &str, finish_reason: serde_json::Value| {
+            serde_json::json!({
+                "id": "cmpl-1",
+                "object": "text_completion",
+                "created": 1700000000,
+                "model": "gpt-3.5-turbo-instruct",
+                "choices": [{ "text": text, "index": 0, "logprobs": null, "finish_reason": finish_reason }]
+            })
+        };
+        let body = format!(
+            "data: {}\n\ndata: {}\n\ndata: [DONE]\n\n",
+            chunk("This is", serde_json::Value::Null),
+            chunk(" a test.", "stop".into())
+        );
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/completions"))
+            .and(body_partial_json(serde_json::json!({ "stream": true })))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let req =
+            CompletionRequest::new(ChatCompleteModel::Gpt3TurboInstruct, "Say this is a test");
+        let mut text = String::new();
+        let mut finish_reason = None;
+        sdk.completion_stream(req, |res| {
+            text.push_str(&res.choices[0].text);
+            finish_reason = finish_reason.or(res.choices[0].finish_reason);
+        })
+        .await
+        .unwrap();
+        assert_eq!(text, "This is a test.");
+        assert_eq!(finish_reason, Some(FinishReason::Stop));
+    }
+
+    #[tokio::test]
+    async fn chat_should_use_default_model() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .and(body_partial_json(serde_json::json!({ "model": "o3" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1702685778,
+                "model": "o3",
+                "system_fingerprint": null,
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "Hello!" },
+                    "finish_reason": "stop"
+                }],
+                "usage": { "prompt_tokens": 9, "completion_tokens": 2, "total_tokens": 11 }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdkBuilder::default()
+            .token("token")
+            .base_url(server.uri())
+            .default_model(ChatCompleteModel::O3)
+            .build()
+            .unwrap();
+        let res = sdk
+            .chat(vec![ChatCompletionMessage::new_user("Hi", "")])
+            .await
+            .unwrap();
+        assert_eq!(res.model, ChatCompleteModel::O3);
+    }
+
+    #[tokio::test]
+    async fn chat_should_reject_empty_messages() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let err = sdk.chat(vec![]).await.unwrap_err();
+        assert!(matches!(err, LlmError::Validation(_)));
+    }
+
+    #[tokio::test]
+    async fn chat_completion_with_meta_should_capture_request_id() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("x-request-id", "req_123")
+                    .insert_header("x-ratelimit-remaining-requests", "59")
+                    .set_body_json(serde_json::json!({
+                        "id": "chatcmpl-123",
+                        "object": "chat.completion",
+                        "created": 1702685778,
+                        "model": "gpt-4o-mini",
+                        "system_fingerprint": null,
+                        "choices": [],
+                        "usage": { "prompt_tokens": 9, "completion_tokens": 0, "total_tokens": 9 }
+                    })),
+            )
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let req = ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4oMini,
+            vec![ChatCompletionMessage::new_user("Hi", "")],
+        );
+        let (res, meta) = sdk.chat_completion_with_meta(req).await.unwrap();
+        assert_eq!(res.id, "chatcmpl-123");
+        assert_eq!(meta.status, 200);
+        assert_eq!(meta.request_id.as_deref(), Some("req_123"));
+        assert_eq!(meta.headers["x-ratelimit-remaining-requests"], "59");
+    }
+
+    #[tokio::test]
+    async fn with_meta_should_capture_request_id_of_any_method() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("x-request-id", "req_embedding")
+                    .set_body_json(embedding_response()),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("x-request-id", "req_stream")
+                    .set_body_raw("data: [DONE]\n\n", "text/event-stream"),
+            )
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let (res, meta) = with_meta(sdk.embedding(EmbeddingRequest::new("hello")))
+            .await
+            .unwrap();
+        assert_eq!(res.data.len(), 1);
+        assert_eq!(meta.request_id.as_deref(), Some("req_embedding"));
+
+        let req = ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4oMini,
+            vec![ChatCompletionMessage::new_user("Hi", "")],
+        );
+        let (_, meta) = with_meta(sdk.chat_stream(req, |_| {})).await.unwrap();
+        assert_eq!(meta.status, 200);
+        assert_eq!(meta.request_id.as_deref(), Some("req_stream"));
+    }
+
+    fn tool_call_response(tool_calls: serde_json::Value) -> serde_json::Value {
+        serde_json::json!({
+            "id": "chatcmpl-123",
+            "object": "chat.completion",
+            "created": 1702685778,
+            "model": "gpt-4o-mini",
+            "system_fingerprint": null,
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": null, "tool_calls": tool_calls },
+                "finish_reason": "tool_calls"
+            }],
+            "usage": { "prompt_tokens": 9, "completion_tokens": 2, "total_tokens": 11 }
+        })
+    }
+
+    fn add_registry() -> ToolRegistry {
+        #[derive(serde::Deserialize, JsonSchema)]
+        struct AddArgs {
+            a: i64,
+            b: i64,
+        }
+        let mut registry = ToolRegistry::new();
+        registry.register("add", "Add two numbers.", |args: AddArgs| {
+            Ok::<_, String>(serde_json::json!(args.a + args.b))
+        });
+        registry
+    }
+
+    #[tokio::test]
+    async fn run_with_tools_should_dispatch_until_stop() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(tool_call_response(
+                serde_json::json!([
+                    { "id": "call_1", "type": "function", "function": { "name": "add", "arguments": "{\"a\":1,\"b\":2}" } },
+                    { "id": "call_2", "type": "function", "function": { "name": "add", "arguments": "{\"a\":1}" } }
+                ]),
+            )))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .and(body_partial_json(serde_json::json!({
+                "tools": [{ "type": "function", "function": { "name": "add" } }],
+                "messages": [
+                    { "role": "user" },
+                    { "role": "assistant" },
+                    { "role": "tool", "content": "3", "tool_call_id": "call_1" },
+                    { "role": "tool", "tool_call_id": "call_2" }
+                ]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "chatcmpl-456",
+                "object": "chat.completion",
+                "created": 1702685778,
+                "model": "gpt-4o-mini",
+                "system_fingerprint": null,
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "1 + 2 = 3" },
+                    "finish_reason": "stop"
+                }],
+                "usage": { "prompt_tokens": 9, "completion_tokens": 2, "total_tokens": 11 }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let req = ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4oMini,
+            vec![ChatCompletionMessage::new_user("What is 1 + 2?", "")],
+        );
+        let message = sdk
+            .run_with_tools(req, &mut add_registry(), 3)
+            .await
+            .unwrap();
+        assert_eq!(message.content.as_deref(), Some("1 + 2 = 3"));
+    }
+
+    #[tokio::test]
+    async fn run_with_tools_should_stop_after_max_turns() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(tool_call_response(
+                serde_json::json!([
+                    { "id": "call_1", "type": "function", "function": { "name": "add", "arguments": "{\"a\":1,\"b\":2}" } }
+                ]),
+            )))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let req = ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4oMini,
+            vec![ChatCompletionMessage::new_user("What is 1 + 2?", "")],
+        );
+        let err = sdk
+            .run_with_tools(req, &mut add_registry(), 2)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, LlmError::MaxTurnsExceeded(2)));
+    }
+
+    #[tokio::test]
+    async fn run_with_tools_should_be_cancelled_between_turns() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(tool_call_response(serde_json::json!([
+                        { "id": "call_1", "type": "function", "function": { "name": "add", "arguments": "{\"a\":1,\"b\":2}" } }
+                    ])))
+                    .set_delay(Duration::from_millis(200)),
+            )
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let req = ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4oMini,
+            vec![ChatCompletionMessage::new_user("What is 1 + 2?", "")],
+        );
+        // cancelled during the second turn
+        let err = sdk
+            .run_with_tools_cancellable(
+                req,
+                &mut add_registry(),
+                10,
+                tokio::time::sleep(Duration::from_millis(300)),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, LlmError::Cancelled));
+    }
+
+    #[tokio::test]
+    async fn run_with_tools_should_fail_without_choices() {
+        let mut body = tool_call_response(serde_json::json!([]));
+        body["choices"] = serde_json::json!([]);
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let req = ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4oMini,
+            vec![ChatCompletionMessage::new_user("What is 1 + 2?", "")],
+        );
+        let err = sdk
+            .run_with_tools(req, &mut add_registry(), 2)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, LlmError::Decode(_)));
+    }
+
+    #[test]
+    fn from_env_should_read_standard_variables() {
+        let vars = |pairs: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                pairs
+                    .iter()
+                    .find(|(k, _)| *k == name)
+                    .map(|(_, v)| v.to_string())
+            }
+        };
+        let sdk = LlmSdk::from_env_with(vars(&[
+            ("OPENAI_API_KEY", "token"),
+            ("OPENAI_BASE_URL", "http://localhost:8080/v1"),
+            ("OPENAI_ORG_ID", "org-123"),
+        ]))
+        .unwrap();
+        assert_eq!(sdk.token, "token");
+        assert_eq!(sdk.base_url, "http://localhost:8080/v1");
+        assert_eq!(sdk.organization.as_deref(), Some("org-123"));
+        assert_eq!(sdk.project, None);
+
+        let sdk = LlmSdk::from_env_with(vars(&[("OPENAI_API_KEY", "token")])).unwrap();
+        assert_eq!(sdk.base_url, "https://api.openai.com/v1");
+
+        for pairs in [&[][..], &[("OPENAI_API_KEY", "")][..]] {
+            let err = LlmSdk::from_env_with(vars(pairs)).unwrap_err();
+            assert!(matches!(err, LlmError::Config(_)));
+        }
+    }
+
+    #[tokio::test]
+    async fn chat_stream_by_index_should_demux_choices() {
+        let chunk = |index: usize, content: &str| {
+            format!(
+                "data: {}\n\n",
+                serde_json::json!({
+                    "id": "chatcmpl-123",
+                    "object": "chat.completion.chunk",
+                    "created": 1702685778,
+                    "model": "gpt-4o-mini",
+                    "system_fingerprint": null,
+                    "choices": [{ "index": index, "delta": { "content": content }, "finish_reason": null, "logprobs": null }]
+                })
+            )
+        };
+        let body = [
+            chunk(0, "Hel"),
+            chunk(1, "Bon"),
+            chunk(1, "jour"),
+            chunk(0, "lo"),
+        ]
+        .concat()
+            + "data: [DONE]\n\n";
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", server.uri());
+        let req = ChatCompletionRequestBuilder::default()
+            .messages(vec![ChatCompletionMessage::new_user("Greet me", "")])
+            .n(2usize)
+            .stream(true)
+            .build()
+            .unwrap();
+        let mut completions = std::collections::BTreeMap::<usize, String>::new();
+        sdk.chat_stream_by_index(req, |index, delta| {
+            completions
+                .entry(index)
+                .or_default()
+                .push_str(delta.content.as_deref().unwrap_or_default());
+        })
+        .await
+        .unwrap();
+        assert_eq!(completions[&0], "Hello");
+        assert_eq!(completions[&1], "Bonjour");
+    }
+
+    struct CannedResponse(serde_json::Value);
+
+    #[async_trait::async_trait]
+    impl reqwest_middleware::Middleware for CannedResponse {
+        async fn handle(
+            &self,
+            req: reqwest::Request,
+            _extensions: &mut task_local_extensions::Extensions,
+            _next: reqwest_middleware::Next<'_>,
+        ) -> reqwest_middleware::Result<Response> {
+            assert_eq!(req.url().path(), "/v1/embeddings");
+            let res = http::Response::builder()
+                .status(200)
+                .header("content-type", "application/json")
+                .body(self.0.to_string())
+                .unwrap();
+            Ok(res.into())
+        }
+    }
+
+    #[tokio::test]
+    async fn with_client_should_use_mock_transport() {
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(CannedResponse(embedding_response()))
+            .build();
+        let sdk = LlmSdk::with_client("token", client);
+        let res = sdk.embedding(EmbeddingRequest::new("hello")).await.unwrap();
+        assert_eq!(res.data[0].embedding, vec![0.1, 0.2]);
+    }
+
+    #[tokio::test]
+    async fn base_url_trailing_slash_should_be_stripped() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(embedding_response()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let sdk = LlmSdk::new_with_base_url("token", format!("{}/v1/", server.uri()));
+        assert_eq!(sdk.base_url, format!("{}/v1", server.uri()));
+        sdk.embedding(EmbeddingRequest::new("hello")).await.unwrap();
+    }
 }
@@ -0,0 +1,58 @@
+use crate::LlmError;
+use reqwest::{header::HeaderMap, Response};
+use std::{cell::RefCell, future::Future};
+
+tokio::task_local! {
+    /// The metadata of the latest response within `with_meta`.
+    static META: RefCell<Option<ResponseMeta>>;
+}
+
+/// The metadata of a successful response, captured before the body is consumed. See `with_meta`.
+#[derive(Debug, Clone, Default)]
+pub struct ResponseMeta {
+    /// The HTTP status code of the response.
+    pub status: u16,
+    /// The response headers, e.g. the `x-ratelimit-*` headers.
+    pub headers: HeaderMap,
+    /// The `x-request-id` header, which OpenAI asks for in support requests.
+    pub request_id: Option<String>,
+}
+
+impl ResponseMeta {
+    pub(crate) fn new(res: &Response) -> Self {
+        let headers = res.headers().clone();
+        Self {
+            status: res.status().as_u16(),
+            request_id: request_id(&headers),
+            headers,
+        }
+    }
+}
+
+/// Run an SDK call, also returning the metadata of its response, e.g. `with_meta(sdk.embedding(req))` for the
+/// `x-request-id` of the embedding. It works with every method: streaming methods capture the metadata once the
+/// response starts, and for calls sending several requests, e.g. `LlmSdk::run_with_tools`, it is the metadata of the
+/// last response. The call must be awaited within, not spawned onto another task.
+pub async fn with_meta<T>(
+    fut: impl Future<Output = Result<T, LlmError>>,
+) -> Result<(T, ResponseMeta), LlmError> {
+    let (ret, meta) = META
+        .scope(RefCell::new(None), async {
+            let ret = fut.await;
+            (ret, META.with(|meta| meta.take()))
+        })
+        .await;
+    Ok((ret?, meta.unwrap_or_default()))
+}
+
+/// Capture the metadata of a successful response for `with_meta`, if the call runs within it.
+pub(crate) fn capture(res: &Response) {
+    let _ = META.try_with(|meta| *meta.borrow_mut() = Some(ResponseMeta::new(res)));
+}
+
+pub(crate) fn request_id(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .map(String::from)
+}
@@ -1,10 +1,20 @@
-use reqwest::{header, Request, Response};
+use reqwest::{header, Request, Response, StatusCode};
 use reqwest_middleware::{Middleware, Next, Result};
-use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
+use reqwest_retry::{policies::ExponentialBackoff, RetryPolicy, Retryable};
+use retry_policies::RetryDecision;
+use std::{
+    fmt,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 use task_local_extensions::Extensions;
+use tracing::warn;
 
+/// Retries transient failures, i.e. connection errors, timeouts, 408, 429 and 5xx responses. Other client errors
+/// such as 400 are never retried. If the server suggests a delay (`retry-after-ms`, `retry-after`, or
+/// `x-ratelimit-reset-*` for 429), it is used instead of the exponential backoff, capped at the maximum retry interval.
 pub(crate) struct RetryMiddleware {
-    inner: RetryTransientMiddleware<ExponentialBackoff>,
+    policy: ExponentialBackoff,
 }
 
 #[async_trait::async_trait]
@@ -18,26 +28,260 @@ impl Middleware for RetryMiddleware {
         // check if req is cloneable without using try_clone
         // check request header - if content-type is multipart/form-data, then don't retry
         match req.headers().get(header::CONTENT_TYPE).map(|v| v.to_str()) {
-            Some(Ok(content_type)) => {
+            Some(Ok(content_type))
                 if content_type.contains("multipart/form-data")
-                    || content_type == "application/octet-stream"
-                {
-                    next.run(req, extensions).await
-                } else {
-                    // what about other content types? But at least for OpenAI APIs, we only see multipart/form-data as non-retryable
-                    self.inner.handle(req, extensions, next).await
+                    || content_type == "application/octet-stream" =>
+            {
+                next.run(req, extensions).await
+            }
+            // what about other content types? But at least for OpenAI APIs, we only see multipart/form-data as non-retryable
+            _ => self.execute_with_retry(req, extensions, next).await,
+        }
+    }
+}
+
+impl RetryMiddleware {
+    async fn execute_with_retry(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        let mut n_past_retries = 0;
+        loop {
+            let Some(duplicate) = req.try_clone() else {
+                // streaming bodies can't be replayed
+                return next.run(req, extensions).await;
+            };
+            let result = next.clone().run(duplicate, extensions).await;
+            if Retryable::from_reqwest_response(&result) != Some(Retryable::Transient) {
+                return result;
+            }
+            let backoff = match self.policy.should_retry(n_past_retries) {
+                RetryDecision::Retry { execute_after } => {
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_millis() as i64;
+                    Duration::from_millis((execute_after.timestamp_millis() - now).max(0) as u64)
+                }
+                RetryDecision::DoNotRetry => return result,
+            };
+            let delay = result
+                .as_ref()
+                .ok()
+                .and_then(suggested_delay)
+                .map(|d| d.min(self.policy.max_retry_interval))
+                .unwrap_or(backoff);
+            warn!(
+                "Retry attempt #{}. Sleeping {:?} before the next attempt",
+                n_past_retries, delay
+            );
+            tokio::time::sleep(delay).await;
+            n_past_retries += 1;
+        }
+    }
+}
+
+impl From<ExponentialBackoff> for RetryMiddleware {
+    fn from(policy: ExponentialBackoff) -> Self {
+        Self { policy }
+    }
+}
+
+/// Fails fast during outages: after `threshold` consecutive failures (transport errors or 5xx responses, after
+/// retries), requests are rejected with `CircuitOpen` until `cooldown` has passed. The next request then goes
+/// through as a probe (half-open), while the others are still rejected; a successful probe closes the circuit again,
+/// a failed one reopens it for another cooldown.
+pub(crate) struct CircuitBreakerMiddleware {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<CircuitState>,
+}
+
+#[derive(Default)]
+struct CircuitState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+    /// Whether the probe of the half-open circuit is in flight.
+    probing: bool,
+}
+
+/// The in-flight probe of a half-open circuit. Dropping it, e.g. when the request is cancelled, lets the next request
+/// probe instead.
+struct Probe<'a>(&'a Mutex<CircuitState>);
+
+impl Drop for Probe<'_> {
+    fn drop(&mut self) {
+        self.0.lock().unwrap().probing = false;
+    }
+}
+
+/// The error of a request rejected by an open circuit, see `CircuitBreakerMiddleware`.
+#[derive(Debug)]
+pub(crate) struct CircuitOpen(pub(crate) Duration);
+
+impl fmt::Display for CircuitOpen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "circuit breaker open, retry in {:?}", self.0)
+    }
+}
+
+impl std::error::Error for CircuitOpen {}
+
+impl CircuitBreakerMiddleware {
+    pub(crate) fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            state: Mutex::default(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for CircuitBreakerMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        let probe = {
+            let mut state = self.state.lock().unwrap();
+            match state.open_until {
+                Some(open_until) => {
+                    let now = Instant::now();
+                    let retry_in = if now < open_until {
+                        Some(open_until - now)
+                    } else if state.probing {
+                        // the outcome of the probe is unknown yet
+                        Some(self.cooldown)
+                    } else {
+                        None
+                    };
+                    if let Some(retry_in) = retry_in {
+                        return Err(reqwest_middleware::Error::middleware(CircuitOpen(retry_in)));
+                    }
+                    state.probing = true;
+                    Some(Probe(&self.state))
                 }
+                None => None,
             }
-            _ => {
-                // does this mean, no body?
-                self.inner.handle(req, extensions, next).await
+        };
+        let result = next.run(req, extensions).await;
+        drop(probe);
+        let failed = match &result {
+            Ok(res) => res.status().is_server_error(),
+            Err(_) => true,
+        };
+        let mut state = self.state.lock().unwrap();
+        if failed {
+            state.consecutive_failures += 1;
+            if state.consecutive_failures >= self.threshold {
+                warn!(
+                    "{} consecutive failures, opening the circuit for {:?}",
+                    state.consecutive_failures, self.cooldown
+                );
+                state.open_until = Some(Instant::now() + self.cooldown);
             }
+        } else {
+            *state = CircuitState::default();
         }
+        result
     }
 }
 
-impl From<RetryTransientMiddleware<ExponentialBackoff>> for RetryMiddleware {
-    fn from(inner: RetryTransientMiddleware<ExponentialBackoff>) -> Self {
-        Self { inner }
+/// The delay suggested by the server. `retry-after-ms` and `retry-after` (in seconds) take precedence over the
+/// OpenAI rate limit headers, which tell when the request and token limits are reset, e.g. `1s` or `6m0s`. As these
+/// are sent with every response, they are only used for 429 responses; other failures fall back to the backoff.
+pub(crate) fn suggested_delay(res: &Response) -> Option<Duration> {
+    let get = |name: &str| res.headers().get(name)?.to_str().ok();
+    if let Some(ms) = get("retry-after-ms").and_then(|v| v.trim().parse::<f64>().ok()) {
+        return Duration::try_from_secs_f64(ms / 1000.0).ok();
+    }
+    if let Some(secs) = get("retry-after").and_then(|v| v.trim().parse::<f64>().ok()) {
+        return Duration::try_from_secs_f64(secs).ok();
+    }
+    if res.status() != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    ["x-ratelimit-reset-requests", "x-ratelimit-reset-tokens"]
+        .into_iter()
+        .filter_map(|name| get(name).and_then(parse_duration))
+        .max()
+}
+
+/// Parse durations like `20ms`, `1.5s` or `1h6m0s`.
+fn parse_duration(s: &str) -> Option<Duration> {
+    let mut rest = s.trim();
+    if rest.is_empty() {
+        return None;
+    }
+    let mut total = 0.0;
+    while !rest.is_empty() {
+        let end = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .filter(|&i| i > 0)?;
+        let value: f64 = rest[..end].parse().ok()?;
+        rest = &rest[end..];
+        let unit_end = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        let unit = match &rest[..unit_end] {
+            "ms" => 0.001,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            _ => return None,
+        };
+        total += value * unit;
+        rest = &rest[unit_end..];
+    }
+    Duration::try_from_secs_f64(total).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_should_work() {
+        assert_eq!(parse_duration("20ms"), Some(Duration::from_millis(20)));
+        assert_eq!(parse_duration("1.5s"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("6m0s"), Some(Duration::from_secs(360)));
+        assert_eq!(parse_duration("1h2m3s"), Some(Duration::from_secs(3723)));
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("soon"), None);
+        assert_eq!(parse_duration("5"), None);
+    }
+
+    fn response(status: u16, headers: &[(&str, &str)]) -> Response {
+        let mut builder = http::Response::builder().status(status);
+        for (k, v) in headers {
+            builder = builder.header(*k, *v);
+        }
+        builder.body("").unwrap().into()
+    }
+
+    #[test]
+    fn suggested_delay_should_use_rate_limit_reset_only_for_429() {
+        let reset = [
+            ("x-ratelimit-reset-requests", "1s"),
+            ("x-ratelimit-reset-tokens", "6m0s"),
+        ];
+        assert_eq!(
+            suggested_delay(&response(429, &reset)),
+            Some(Duration::from_secs(360))
+        );
+        assert_eq!(suggested_delay(&response(500, &reset)), None);
+        assert_eq!(
+            suggested_delay(&response(503, &[("retry-after", "2")])),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(
+            suggested_delay(&response(408, &[("retry-after-ms", "250")])),
+            Some(Duration::from_millis(250))
+        );
     }
 }
@@ -0,0 +1,124 @@
+use crate::{ChatCompleteModel, ChatCompleteUsage};
+use std::{collections::HashMap, sync::OnceLock};
+
+/// The price of a model in USD per 1M tokens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelPrice {
+    /// Price per 1M prompt tokens.
+    pub input: f64,
+    /// Price per 1M completion tokens.
+    pub output: f64,
+}
+
+/// A table of model prices. `PriceTable::default()` contains the public OpenAI prices of the known models; insert
+/// your own prices for custom or self-hosted models, or to override the defaults.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceTable {
+    prices: HashMap<ChatCompleteModel, ModelPrice>,
+}
+
+impl ModelPrice {
+    pub const fn new(input: f64, output: f64) -> Self {
+        Self { input, output }
+    }
+}
+
+impl PriceTable {
+    /// An empty price table.
+    pub fn new() -> Self {
+        Self {
+            prices: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, model: ChatCompleteModel, price: ModelPrice) -> &mut Self {
+        self.prices.insert(model, price);
+        self
+    }
+
+    pub fn get(&self, model: &ChatCompleteModel) -> Option<&ModelPrice> {
+        self.prices.get(model)
+    }
+
+    fn default_table() -> &'static PriceTable {
+        static TABLE: OnceLock<PriceTable> = OnceLock::new();
+        TABLE.get_or_init(PriceTable::default)
+    }
+}
+
+impl Default for PriceTable {
+    fn default() -> Self {
+        let mut table = Self::new();
+        table
+            .insert(ChatCompleteModel::Gpt3Turbo, ModelPrice::new(1.0, 2.0))
+            .insert(
+                ChatCompleteModel::Gpt3TurboInstruct,
+                ModelPrice::new(1.5, 2.0),
+            )
+            .insert(ChatCompleteModel::Gpt4Turbo, ModelPrice::new(10.0, 30.0))
+            .insert(
+                ChatCompleteModel::Gpt4TurboLatest,
+                ModelPrice::new(10.0, 30.0),
+            )
+            .insert(
+                ChatCompleteModel::Gpt4TurboVision,
+                ModelPrice::new(10.0, 30.0),
+            )
+            .insert(ChatCompleteModel::Gpt4o, ModelPrice::new(2.5, 10.0))
+            .insert(ChatCompleteModel::Gpt4oMini, ModelPrice::new(0.15, 0.6))
+            .insert(ChatCompleteModel::O1, ModelPrice::new(15.0, 60.0))
+            .insert(ChatCompleteModel::O3, ModelPrice::new(2.0, 8.0));
+        table
+    }
+}
+
+impl ChatCompleteUsage {
+    /// Estimate the cost in USD with the default price table. Returns None if the price of the model is unknown.
+    pub fn estimated_cost(&self, model: &ChatCompleteModel) -> Option<f64> {
+        self.estimated_cost_with(model, PriceTable::default_table())
+    }
+
+    /// Estimate the cost in USD with the given price table.
+    pub fn estimated_cost_with(
+        &self,
+        model: &ChatCompleteModel,
+        table: &PriceTable,
+    ) -> Option<f64> {
+        let price = table.get(model)?;
+        Some(
+            (self.prompt_tokens as f64 * price.input
+                + self.completion_tokens as f64 * price.output)
+                / 1_000_000.0,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage() -> ChatCompleteUsage {
+        serde_json::from_value(serde_json::json!({
+            "prompt_tokens": 1000,
+            "completion_tokens": 500,
+            "total_tokens": 1500
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn estimated_cost_should_work() {
+        let cost = usage()
+            .estimated_cost(&ChatCompleteModel::Gpt4Turbo)
+            .unwrap();
+        assert!((cost - 0.025).abs() < 1e-9);
+
+        let custom = ChatCompleteModel::Other("my-llama".into());
+        assert_eq!(usage().estimated_cost(&custom), None);
+
+        let mut table = PriceTable::default();
+        table.insert(custom.clone(), ModelPrice::new(0.2, 0.2));
+        let cost = usage().estimated_cost_with(&custom, &table).unwrap();
+        assert!((cost - 0.0003).abs() < 1e-9);
+    }
+}
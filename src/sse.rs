@@ -0,0 +1,129 @@
+use crate::LlmError;
+
+/// An incremental decoder of server-sent events, following the event stream format of the HTML spec: lines end with
+/// `\n`, `\r\n` or `\r`, a blank line dispatches the event, `:` starts a comment, and the `data` fields of an event
+/// are joined with `\n`. The other fields (`event`, `id` and `retry`) are ignored, as the payloads carry everything
+/// the SDK needs.
+#[derive(Debug, Default)]
+pub(crate) struct SseDecoder {
+    /// The bytes of the incomplete line.
+    buf: Vec<u8>,
+    /// How far `buf` has been scanned for a line ending, so each byte is scanned once.
+    scanned: usize,
+    /// The data of the current event, if it has a `data` field.
+    data: Option<String>,
+}
+
+impl SseDecoder {
+    /// Feed a chunk of the body, returning the data of the events it completes.
+    pub(crate) fn push(&mut self, chunk: &[u8]) -> Result<Vec<String>, LlmError> {
+        self.buf.extend_from_slice(chunk);
+        let buf = std::mem::take(&mut self.buf);
+        let mut events = Vec::new();
+        let mut start = 0;
+        let mut i = self.scanned;
+        while i < buf.len() {
+            let next = match buf[i] {
+                b'\n' => i + 1,
+                // wait for the next chunk to tell `\r` from `\r\n`
+                b'\r' if i + 1 == buf.len() => break,
+                b'\r' if buf[i + 1] == b'\n' => i + 2,
+                b'\r' => i + 1,
+                _ => {
+                    i += 1;
+                    continue;
+                }
+            };
+            self.process_line(&buf[start..i], &mut events)?;
+            start = next;
+            i = next;
+        }
+        self.buf = buf;
+        self.buf.drain(..start);
+        self.scanned = i - start;
+        Ok(events)
+    }
+
+    /// Flush the decoder at the end of the body. Unlike the spec, an event without the final blank line is still
+    /// dispatched, as some servers omit it.
+    pub(crate) fn finish(mut self) -> Result<Option<String>, LlmError> {
+        let mut events = Vec::new();
+        let buf = std::mem::take(&mut self.buf);
+        let line = buf.strip_suffix(b"\r").unwrap_or(&buf);
+        if !line.is_empty() {
+            self.process_line(line, &mut events)?;
+        }
+        self.process_line(b"", &mut events)?;
+        Ok(events.pop())
+    }
+
+    fn process_line(&mut self, line: &[u8], events: &mut Vec<String>) -> Result<(), LlmError> {
+        let line = std::str::from_utf8(line).map_err(|e| LlmError::Stream(e.to_string()))?;
+        if line.is_empty() {
+            events.extend(self.data.take());
+            return Ok(());
+        }
+        if line.starts_with(':') {
+            return Ok(());
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        if field == "data" {
+            match &mut self.data {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                }
+                None => self.data = Some(value.to_owned()),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(chunks: &[&str]) -> Vec<String> {
+        let mut decoder = SseDecoder::default();
+        let mut events = Vec::new();
+        for chunk in chunks {
+            events.extend(decoder.push(chunk.as_bytes()).unwrap());
+        }
+        events.extend(decoder.finish().unwrap());
+        events
+    }
+
+    #[test]
+    fn sse_decoder_should_follow_the_spec() {
+        let body = ": keep-alive\n\nevent: message\nid: 1\nretry: 1000\ndata: {\"a\":1}\n\ndata:{\"b\":2}\n\n";
+        assert_eq!(decode(&[body]), [r#"{"a":1}"#, r#"{"b":2}"#]);
+
+        // multi-line data, and events without data are skipped
+        let body = "data: first\ndata:  second\n\nevent: ping\n\ndata\n\n";
+        assert_eq!(decode(&[body]), ["first\n second", ""]);
+
+        // all line endings
+        let body = "data: a\r\n\r\ndata: b\r\rdata: c\n\n";
+        assert_eq!(decode(&[body]), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn sse_decoder_should_buffer_across_chunks() {
+        let chunks = ["da", "ta: {\"a\"", ":1}\r", "\n\r", "\ndata: b"];
+        assert_eq!(decode(&chunks), [r#"{"a":1}"#, "b"]);
+
+        // a long line split into many chunks
+        let data = "x".repeat(10_000);
+        let body = format!("data: {}\n\n", data);
+        let chunks: Vec<_> = body
+            .as_bytes()
+            .chunks(7)
+            .map(|c| std::str::from_utf8(c).unwrap())
+            .collect();
+        assert_eq!(decode(&chunks), [data]);
+    }
+}
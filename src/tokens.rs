@@ -0,0 +1,234 @@
+use crate::{ChatCompleteModel, ChatCompletionMessage, ContentPart, MessageContent};
+use std::sync::OnceLock;
+use tiktoken_rs::{
+    cl100k_base, o200k_base,
+    tokenizer::{get_tokenizer, Tokenizer},
+    CoreBPE,
+};
+
+/// Every message is wrapped as `<|start|>{role/name}\n{content}<|end|>\n`.
+const TOKENS_PER_MESSAGE: usize = 3;
+/// A name takes the role's place next to it.
+const TOKENS_PER_NAME: usize = 1;
+/// Every reply is primed with `<|start|>assistant<|message|>`.
+const TOKENS_PER_REPLY: usize = 3;
+/// The cost of a low detail image, the minimum for any image.
+const TOKENS_PER_IMAGE: usize = 85;
+
+/// Count the prompt tokens of `messages` locally, to trim the context or chunk the input before sending. The count
+/// follows the OpenAI cookbook: the message contents and names are encoded with the tokenizer of the model, plus the
+/// fixed overhead per message, per name and for priming the reply. It is an approximation: tool definitions are not
+/// included, and each image counts as a low detail image (85 tokens), as the size of a high detail image is unknown.
+/// Models unknown to the tokenizer, e.g. o1 or `Other` models, are counted with o200k_base.
+pub fn count_tokens(model: &ChatCompleteModel, messages: &[ChatCompletionMessage]) -> usize {
+    let bpe = bpe_for(model);
+    TOKENS_PER_REPLY
+        + messages
+            .iter()
+            .map(|message| message_tokens(bpe, message))
+            .sum::<usize>()
+}
+
+/// Drop the oldest messages until `messages` fit `ChatCompleteModel::context_window` with `max_tokens` left for the
+/// completion. System messages are always kept, and so is the latest message, thus the conversation may still not
+/// fit if they alone exceed the budget. The tool results following a dropped assistant message are dropped along with
+/// it, as the API rejects tool messages without the tool call; if the latest message is one of them, the whole group
+/// is kept and trimming stops. Returns the number of dropped messages.
+pub fn trim_to_fit(
+    messages: &mut Vec<ChatCompletionMessage>,
+    model: &ChatCompleteModel,
+    max_tokens: usize,
+) -> usize {
+    if messages.is_empty() {
+        return 0;
+    }
+    let bpe = bpe_for(model);
+    let budget = model.context_window().saturating_sub(max_tokens);
+    let mut tokens: Vec<_> = messages.iter().map(|m| message_tokens(bpe, m)).collect();
+    let mut total = TOKENS_PER_REPLY + tokens.iter().sum::<usize>();
+    let len = messages.len();
+    while total > budget {
+        let last = messages.len() - 1;
+        let Some(i) = (0..last).find(|&i| !matches!(messages[i], ChatCompletionMessage::System(_)))
+        else {
+            break;
+        };
+        let mut end = i + 1;
+        while end < messages.len() && matches!(messages[end], ChatCompletionMessage::Tool(_)) {
+            end += 1;
+        }
+        // the latest message is a result of the tool calls, keep the whole group
+        if end > last {
+            break;
+        }
+        messages.drain(i..end);
+        total -= tokens.drain(i..end).sum::<usize>();
+    }
+    len - messages.len()
+}
+
+fn message_tokens(bpe: &CoreBPE, message: &ChatCompletionMessage) -> usize {
+    let encode = |text: &str| bpe.encode_with_special_tokens(text).len();
+    let mut num_tokens = TOKENS_PER_MESSAGE;
+    let name = match message {
+        ChatCompletionMessage::System(m) => {
+            num_tokens += encode("system") + encode(m.content());
+            m.name()
+        }
+        ChatCompletionMessage::User(m) => {
+            num_tokens += encode("user");
+            num_tokens += match m.content() {
+                MessageContent::Text(text) => encode(text),
+                MessageContent::Parts(parts) => parts
+                    .iter()
+                    .map(|part| match part {
+                        ContentPart::Text { text } => encode(text),
+                        ContentPart::ImageUrl { .. } => TOKENS_PER_IMAGE,
+                    })
+                    .sum(),
+            };
+            m.name()
+        }
+        ChatCompletionMessage::Assistant(m) => {
+            num_tokens += encode("assistant");
+            num_tokens += m.content.as_deref().map_or(0, encode);
+            num_tokens += m
+                .tool_calls
+                .iter()
+                .map(|call| encode(&call.function.name) + encode(&call.function.arguments))
+                .sum::<usize>();
+            m.name.as_deref()
+        }
+        ChatCompletionMessage::Tool(m) => {
+            num_tokens += encode("tool") + encode(m.content());
+            None
+        }
+    };
+    if let Some(name) = name {
+        num_tokens += encode(name) + TOKENS_PER_NAME;
+    }
+    num_tokens
+}
+
+fn bpe_for(model: &ChatCompleteModel) -> &'static CoreBPE {
+    static CL100K: OnceLock<CoreBPE> = OnceLock::new();
+    static O200K: OnceLock<CoreBPE> = OnceLock::new();
+    match get_tokenizer(&model.to_string()) {
+        Some(Tokenizer::Cl100kBase) => CL100K.get_or_init(|| cl100k_base().unwrap()),
+        _ => O200K.get_or_init(|| o200k_base().unwrap()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FunctionCall, ToolCall, ToolMessage, ToolType};
+
+    #[test]
+    fn count_tokens_should_work() {
+        let messages = vec![
+            ChatCompletionMessage::new_system("You are a helpful assistant.", ""),
+            ChatCompletionMessage::new_user("Hello, how are you?", ""),
+        ];
+        // 3 + (3 + 1 + 6) + (3 + 1 + 6), the same with both tokenizers
+        assert_eq!(count_tokens(&ChatCompleteModel::Gpt4oMini, &messages), 23);
+        assert_eq!(count_tokens(&ChatCompleteModel::Gpt4Turbo, &messages), 23);
+
+        let named = vec![ChatCompletionMessage::new_user(
+            "Hello, how are you?",
+            "bob",
+        )];
+        assert_eq!(
+            count_tokens(&ChatCompleteModel::Gpt4oMini, &named),
+            count_tokens(&ChatCompleteModel::Gpt4oMini, &messages[1..]) + 1 + TOKENS_PER_NAME
+        );
+
+        let with_image = vec![ChatCompletionMessage::new_user_with_images(
+            "Hello, how are you?",
+            ["https://example.com/cat.png"],
+            "",
+        )];
+        assert_eq!(
+            count_tokens(&ChatCompleteModel::Gpt4o, &with_image),
+            count_tokens(&ChatCompleteModel::Gpt4o, &messages[1..]) + TOKENS_PER_IMAGE
+        );
+
+        let tool_call = vec![ChatCompletionMessage::new_assistant(
+            "",
+            vec![ToolCall {
+                id: "call_1".into(),
+                r#type: ToolType::Function,
+                function: FunctionCall {
+                    name: "get_weather".into(),
+                    arguments: r#"{"city":"Boston"}"#.into(),
+                },
+            }],
+        )];
+        assert!(count_tokens(&ChatCompleteModel::O1, &tool_call) > TOKENS_PER_REPLY + 4);
+        assert_eq!(
+            count_tokens(&ChatCompleteModel::Gpt4o, &[]),
+            TOKENS_PER_REPLY
+        );
+    }
+
+    #[test]
+    fn trim_to_fit_should_work() {
+        let model = ChatCompleteModel::Gpt4o;
+        // 80k tokens each, two of them don't fit in the 128k context window
+        let long = "hello ".repeat(80_000);
+        let mut messages = vec![
+            ChatCompletionMessage::new_system("You are a helpful assistant.", ""),
+            ChatCompletionMessage::new_user("Search for it.", ""),
+            ChatCompletionMessage::new_assistant(
+                "",
+                vec![ToolCall {
+                    id: "call_1".into(),
+                    r#type: ToolType::Function,
+                    function: FunctionCall {
+                        name: "search".into(),
+                        arguments: "{}".into(),
+                    },
+                }],
+            ),
+            ToolMessage::new(long.clone(), "call_1").into(),
+            ChatCompletionMessage::new_user(long, ""),
+            ChatCompletionMessage::new_user("Summarize it.", ""),
+        ];
+        assert!(count_tokens(&model, &messages) > 128_000);
+
+        // the tool result is dropped along with its tool call
+        assert_eq!(trim_to_fit(&mut messages, &model, 4_096), 3);
+        assert_eq!(messages.len(), 3);
+        assert!(matches!(messages[0], ChatCompletionMessage::System(_)));
+        assert!(count_tokens(&model, &messages) <= 128_000 - 4_096);
+
+        // nothing to drop
+        assert_eq!(trim_to_fit(&mut messages, &model, 4_096), 0);
+        // the system and the latest message are kept even if they don't fit
+        assert_eq!(trim_to_fit(&mut messages, &model, 128_000), 1);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(trim_to_fit(&mut vec![], &model, 200_000), 0);
+
+        // a tool call with its results is only dropped as a whole
+        let tool_call = ChatCompletionMessage::new_assistant(
+            "",
+            vec![ToolCall {
+                id: "call_1".into(),
+                r#type: ToolType::Function,
+                function: FunctionCall {
+                    name: "search".into(),
+                    arguments: "{}".into(),
+                },
+            }],
+        );
+        let mut messages = vec![
+            ChatCompletionMessage::new_user("Search for it.", ""),
+            tool_call,
+            ToolMessage::new("result", "call_1").into(),
+            ToolMessage::new("result", "call_2").into(),
+        ];
+        assert_eq!(trim_to_fit(&mut messages, &model, 128_000), 1);
+        assert_eq!(messages.len(), 3);
+        assert!(matches!(messages[0], ChatCompletionMessage::Assistant(_)));
+    }
+}